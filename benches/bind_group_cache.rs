@@ -0,0 +1,98 @@
+//! Compares binding textures through a [`BindGroupCache`], which reuses bind groups across frames, against creating
+//! each one again every frame. Needs a GPU, but no display; run with `cargo bench --bench bind_group_cache`.
+
+use and::BindGroupCache;
+use pollster::FutureExt as _;
+use std::{
+    hint::black_box,
+    time::{
+        Duration, Instant,
+    },
+};
+
+const TEXTURES: usize = 64;
+const FRAMES: u32 = 1000;
+
+fn main() {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions::default()).block_on().expect("No adapter");
+    let (device, _queue) = adapter.request_device(&wgpu::DeviceDescriptor::default(), None)
+        .block_on()
+        .expect("Couldn't request device");
+
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Bench bind group layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true, },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    });
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+    let views = (0..TEXTURES)
+        .map(|_| device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Bench texture"),
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1, },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        }).create_view(&wgpu::TextureViewDescriptor::default()))
+        .collect::<Vec<_>>();
+
+    let create = |view: &wgpu::TextureView| device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Bench bind group"),
+        layout: &layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+        ],
+    });
+
+    let mut cache = BindGroupCache::new();
+    let cached = time(|| {
+        for (i, view) in views.iter().enumerate() {
+            black_box(cache.get_or_create(i, || create(view)));
+        }
+    });
+    let uncached = time(|| {
+        for view in &views {
+            black_box(create(view));
+        }
+    });
+
+    println!("{TEXTURES} textures per frame over {FRAMES} frames:");
+    println!("  cached:   {:?} per frame", cached / FRAMES);
+    println!("  uncached: {:?} per frame", uncached / FRAMES);
+    println!("  {:.1}x less CPU time with the cache", uncached.as_secs_f64() / cached.as_secs_f64());
+}
+
+/// Runs `frame` once to warm up, then times `FRAMES` more runs of it.
+fn time(mut frame: impl FnMut()) -> Duration {
+    frame();
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        frame();
+    }
+    start.elapsed()
+}
@@ -0,0 +1,27 @@
+//! Clips the scene to a circle with the stencil attachment. Each frame masks a circle at the center of the window,
+//! writing the reference into the stencil there, and `Config::stencil` makes the scene only pass where it equals the
+//! reference. The scene's triangle is cut off at the circle's edge, and a faint disc drawn on top shows the mask.
+
+use and::{
+    glam::Vec2,
+    Config, StencilSettings,
+};
+
+fn main() {
+    and::run(Config {
+        stencil: Some(StencilSettings {
+            reference: 1,
+            compare: wgpu::CompareFunction::Equal,
+            ..StencilSettings::default()
+        }),
+        on_frame: Some(Box::new(|state| {
+            let size = state.size();
+            let center = Vec2::new(size.width as f32, size.height as f32) / 2.0;
+            let radius = size.width.min(size.height) as f32 / 5.0;
+
+            state.mask_circle(center, radius);
+            state.draw_circle(center, radius + 2.0, [1.0, 1.0, 1.0, 0.25]);
+        })),
+        ..Config::default()
+    });
+}
@@ -0,0 +1,157 @@
+use crate::{
+    resources::{
+        self,
+        Tracked,
+    },
+    target::Blit,
+};
+
+/// Float texture the frames are averaged into, presented in place of the frame target.
+struct History {
+    _texture: Tracked<wgpu::Texture>,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+impl History {
+    fn new(device: &wgpu::Device, blit: &Blit, format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        let texture = resources::create_texture(device, &wgpu::TextureDescriptor {
+            label: Some("Accumulation history"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1, },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Accumulation history bind group"),
+            layout: &blit.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&blit.sampler),
+                },
+            ],
+        });
+
+        Self { _texture: texture, view, bind_group, }
+    }
+}
+
+/// Averages successive frames for progressive rendering, e.g. converging a noisy path-traced or jittered scene.
+/// The `n`th frame since the last reset is blended in with weight `1 / n`, so the history always holds the plain
+/// average of every frame so far. Past the frame limit the history is left alone, holding the converged result.
+pub struct Accumulation {
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    history: History,
+    frames: u32,
+    max_frames: u32,
+}
+
+impl Accumulation {
+    pub fn new(
+        device: &wgpu::Device, blit: &Blit, format: wgpu::TextureFormat, max_frames: u32, width: u32, height: u32,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("accumulate.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Accumulation pipeline layout"),
+            bind_group_layouts: &[&blit.layout],
+            push_constant_ranges: &[],
+        });
+
+        // Lerps from the history toward the new frame by the blend constant.
+        let weighted = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Constant,
+            dst_factor: wgpu::BlendFactor::OneMinusConstant,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Accumulation pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState { color: weighted, alpha: weighted, }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            format, pipeline,
+            history: History::new(device, blit, format, width, height),
+            frames: 0,
+            max_frames: max_frames.max(1),
+        }
+    }
+
+    /// Starts over from the next frame, e.g. because the camera moved and the history no longer matches.
+    pub fn reset(&mut self) {
+        self.frames = 0;
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, blit: &Blit, width: u32, height: u32) {
+        self.history = History::new(device, blit, self.format, width, height);
+        self.reset();
+    }
+
+    /// Frames averaged into the history since the last reset.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Blends the frame in `source`, bound through [`Blit::layout`], into the history. Returns whether it did, which
+    /// it stops doing once enough frames converged.
+    pub fn accumulate(&mut self, encoder: &mut wgpu::CommandEncoder, source: &wgpu::BindGroup) -> bool {
+        if self.frames >= self.max_frames { return false };
+
+        self.frames += 1;
+        let weight = 1.0 / self.frames as f64;
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Accumulation pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.history.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    // The first frame replaces whatever was left by a weight of 1 anyway.
+                    load: if self.frames == 1 {
+                        wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_blend_constant(wgpu::Color { r: weight, g: weight, b: weight, a: weight, });
+        pass.set_bind_group(0, source, &[]);
+        pass.draw(0..3, 0..1);
+        true
+    }
+
+    /// The averaged frames, bound through [`Blit::layout`] for presenting.
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.history.bind_group
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveResSettings {
+    /// Frame time to stay under, e.g. 16.6ms for 60 FPS.
+    pub target_frame_time: Duration,
+    /// Lowest render scale to go down to, as a fraction of the surface size.
+    pub min_scale: f32,
+    /// Highest render scale to go up to; above 1 supersamples.
+    pub max_scale: f32,
+}
+
+impl Default for AdaptiveResSettings {
+    fn default() -> Self {
+        Self {
+            target_frame_time: Duration::from_micros(16_667),
+            min_scale: 0.5,
+            max_scale: 1.0,
+        }
+    }
+}
+
+/// Steers the render scale toward the target frame time. Frame times are smoothed, there's a dead band around the
+/// target where nothing changes, and every change waits for the average to settle before the next, so the scale
+/// doesn't oscillate between two steps.
+pub struct AdaptiveResolution {
+    settings: AdaptiveResSettings,
+    average: Option<f32>,
+    cooldown: u32,
+}
+
+impl AdaptiveResolution {
+    /// Weight of the newest frame time in the moving average.
+    const SMOOTHING: f32 = 0.1;
+    /// Frames to wait after a change, long enough for the average to reflect it.
+    const COOLDOWN: u32 = 30;
+    /// Lower the scale above this fraction of the target...
+    const SLOW: f32 = 1.1;
+    /// ...and raise it below this one.
+    const FAST: f32 = 0.8;
+    const STEP_DOWN: f32 = 0.9;
+    const STEP_UP: f32 = 1.05;
+
+    pub fn new(settings: AdaptiveResSettings) -> Self {
+        Self { settings, average: None, cooldown: 0, }
+    }
+
+    /// Takes the time the last frame took, returning the new scale if `scale` should change.
+    pub fn update(&mut self, frame_time: Duration, scale: f32) -> Option<f32> {
+        let frame_time = frame_time.as_secs_f32();
+        let average = match self.average {
+            Some(average) => average + (frame_time - average) * Self::SMOOTHING,
+            None => frame_time,
+        };
+        self.average = Some(average);
+
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+            return None;
+        }
+
+        let ratio = average / self.settings.target_frame_time.as_secs_f32();
+        let step = if ratio > Self::SLOW {
+            Self::STEP_DOWN
+        } else if ratio < Self::FAST {
+            Self::STEP_UP
+        } else {
+            return None;
+        };
+
+        let new_scale = (scale * step).clamp(self.settings.min_scale, self.settings.max_scale);
+        if new_scale == scale { return None };
+
+        self.cooldown = Self::COOLDOWN;
+        Some(new_scale)
+    }
+}
@@ -0,0 +1,84 @@
+use crate::AndError;
+use rodio::{
+    Decoder, OutputStream, OutputStreamHandle, Sink, Source as _,
+};
+use std::{
+    io::Cursor,
+    sync::{
+        atomic::{
+            AtomicBool, Ordering,
+        },
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Plays fire-and-forget sound effects and a single looping music track on the default output device. Everything
+/// pauses together while the window is in the background.
+pub struct Audio {
+    // Playback stops once the stream is dropped, so it has to be kept around even though it's never touched.
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+    music: Option<Sink>,
+    music_volume: f32,
+    paused: Arc<AtomicBool>,
+}
+
+impl Audio {
+    /// How often playing sound effects check whether they should pause.
+    const PAUSE_POLL: Duration = Duration::from_millis(10);
+
+    pub fn new() -> Result<Self, AndError> {
+        let (stream, handle) = OutputStream::try_default()?;
+        Ok(Self {
+            _stream: stream,
+            handle,
+            music: None,
+            music_volume: 1.0,
+            paused: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub fn play_sound(&self, data: &[u8]) -> Result<(), AndError> {
+        let paused = self.paused.clone();
+        let source = Decoder::new(Cursor::new(data.to_vec()))?
+            .convert_samples::<f32>()
+            .pausable(paused.load(Ordering::Relaxed))
+            .periodic_access(Self::PAUSE_POLL, move |source| source.set_paused(paused.load(Ordering::Relaxed)));
+
+        self.handle.play_raw(source)?;
+        Ok(())
+    }
+
+    /// Replaces the current music track with `data`, looping forever.
+    pub fn play_music(&mut self, data: Vec<u8>) -> Result<(), AndError> {
+        let source = Decoder::new(Cursor::new(data))?.repeat_infinite();
+        let sink = Sink::try_new(&self.handle)?;
+        sink.set_volume(self.music_volume);
+        if self.paused.load(Ordering::Relaxed) {
+            sink.pause();
+        }
+
+        sink.append(source);
+        self.music = Some(sink);
+        Ok(())
+    }
+
+    pub fn stop_music(&mut self) {
+        self.music = None;
+    }
+
+    pub fn set_music_volume(&mut self, volume: f32) {
+        self.music_volume = volume;
+        if let Some(music) = self.music.as_ref() {
+            music.set_volume(volume);
+        }
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+        if let Some(music) = self.music.as_ref() {
+            if paused { music.pause() } else { music.play() }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+struct VertOut {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+struct Params {
+    // 0 for none, 1 for Reinhard, 2 for ACES.
+    tonemap: u32,
+    encode_srgb: u32,
+    premultiplied: u32,
+    gamma: f32,
+    brightness: f32,
+};
+
+@group(0) @binding(0)
+var frame: texture_2d<f32>;
+@group(0) @binding(1)
+var frame_sampler: sampler;
+@group(1) @binding(0)
+var<uniform> params: Params;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertOut {
+    var out: VertOut;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn linear_to_srgb(x: vec3<f32>) -> vec3<f32> {
+    let low = x * 12.92;
+    let high = 1.055 * pow(x, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, x <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VertOut) -> @location(0) vec4<f32> {
+    let color = textureSample(frame, frame_sampler, in.uv);
+    var rgb = color.rgb;
+    if (params.premultiplied != 0u && color.a > 0.0) {
+        rgb = rgb / color.a;
+    }
+
+    if (params.tonemap == 1u) {
+        rgb = rgb / (rgb + vec3<f32>(1.0));
+    } else if (params.tonemap == 2u) {
+        rgb = aces(rgb);
+    }
+
+    if (params.gamma != 1.0 || params.brightness != 1.0) {
+        rgb = pow(max(rgb * params.brightness, vec3<f32>(0.0)), vec3<f32>(1.0 / params.gamma));
+    }
+
+    if (params.encode_srgb != 0u) {
+        rgb = linear_to_srgb(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+    }
+
+    if (params.premultiplied != 0u) {
+        rgb = rgb * color.a;
+    }
+
+    return vec4<f32>(rgb, color.a);
+}
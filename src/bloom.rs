@@ -0,0 +1,255 @@
+use crate::{
+    resources::{
+        self,
+        Tracked,
+    },
+    target,
+};
+use bytemuck::{
+    Pod, Zeroable,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomSettings {
+    /// Brightness above which pixels start to bleed.
+    pub threshold: f32,
+    /// How strongly the blurred highlights are added back onto the frame.
+    pub intensity: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self { threshold: 1.0, intensity: 0.5, }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct BloomParams {
+    threshold: f32,
+    intensity: f32,
+    _pad: [f32; 2],
+}
+
+impl From<BloomSettings> for BloomParams {
+    fn from(BloomSettings { threshold, intensity, }: BloomSettings) -> Self {
+        Self { threshold, intensity, _pad: [0.0; 2], }
+    }
+}
+
+/// Views and bind groups of the mip chain, recreated whenever the frame is resized.
+struct Chain {
+    _texture: Tracked<wgpu::Texture>,
+    source: wgpu::BindGroup,
+    views: Vec<wgpu::TextureView>,
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+/// Bleeds bright parts of the frame into their surroundings: pixels above the threshold are extracted into a half
+/// resolution texture, blurred down a mip chain, blurred back up it additively, then added onto the frame.
+pub struct Bloom {
+    format: wgpu::TextureFormat,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    params: Tracked<wgpu::Buffer>,
+    params_bind_group: wgpu::BindGroup,
+    prefilter: wgpu::RenderPipeline,
+    downsample: wgpu::RenderPipeline,
+    upsample: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+    chain: Chain,
+}
+
+impl Bloom {
+    const MAX_MIPS: u32 = 6;
+
+    pub fn new(
+        device: &wgpu::Device, format: wgpu::TextureFormat, settings: BloomSettings,
+        frame: &wgpu::TextureView, width: u32, height: u32,
+    ) -> Self {
+        let layout = target::texture_layout(device, "Bloom bind group layout");
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let params = resources::create_buffer_init(device, &wgpu::util::BufferInitDescriptor {
+            label: Some("Bloom params"),
+            contents: bytemuck::bytes_of(&BloomParams::from(settings)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom params bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom params bind group"),
+            layout: &params_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("bloom.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom pipeline layout"),
+            bind_group_layouts: &[&layout, &params_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point, blend| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Bloom pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        // Adds color on top, leaving the destination's alpha alone.
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+
+        let prefilter = pipeline("fs_prefilter", wgpu::BlendState::REPLACE);
+        let downsample = pipeline("fs_blur", wgpu::BlendState::REPLACE);
+        let upsample = pipeline("fs_blur", additive);
+        let composite = pipeline("fs_composite", additive);
+
+        let chain = Self::create_chain(device, &layout, &sampler, format, frame, width, height);
+        Self {
+            format, layout, sampler, params, params_bind_group,
+            prefilter, downsample, upsample, composite, chain,
+        }
+    }
+
+    fn create_chain(
+        device: &wgpu::Device, layout: &wgpu::BindGroupLayout, sampler: &wgpu::Sampler, format: wgpu::TextureFormat,
+        frame: &wgpu::TextureView, width: u32, height: u32,
+    ) -> Chain {
+        let (width, height) = ((width / 2).max(1), (height / 2).max(1));
+        let mips = (32 - width.min(height).leading_zeros()).clamp(1, Self::MAX_MIPS);
+
+        let texture = resources::create_texture(device, &wgpu::TextureDescriptor {
+            label: Some("Bloom chain"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1, },
+            mip_level_count: mips,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+
+        let bind_group = |view: &wgpu::TextureView| device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+
+        let views = (0..mips)
+            .map(|mip| texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Bloom mip"),
+                base_mip_level: mip,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            }))
+            .collect::<Vec<_>>();
+        let bind_groups = views.iter().map(bind_group).collect();
+
+        Chain { source: bind_group(frame), _texture: texture, views, bind_groups, }
+    }
+
+    /// Recreates the mip chain for a resized frame.
+    pub fn resize(&mut self, device: &wgpu::Device, frame: &wgpu::TextureView, width: u32, height: u32) {
+        self.chain = Self::create_chain(device, &self.layout, &self.sampler, self.format, frame, width, height);
+    }
+
+    pub fn set_settings(&self, queue: &wgpu::Queue, settings: BloomSettings) {
+        queue.write_buffer(&self.params, 0, bytemuck::bytes_of(&BloomParams::from(settings)));
+    }
+
+    /// Returns how many passes that took, one per mip level down and up again.
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::TextureView) -> u32 {
+        let Chain { source, views, bind_groups, .. } = &self.chain;
+        let last = views.len() - 1;
+
+        self.pass(encoder, &views[0], true, &self.prefilter, source);
+        for mip in 1..=last {
+            self.pass(encoder, &views[mip], true, &self.downsample, &bind_groups[mip - 1]);
+        }
+        for mip in (0..last).rev() {
+            self.pass(encoder, &views[mip], false, &self.upsample, &bind_groups[mip + 1]);
+        }
+
+        self.pass(encoder, frame, false, &self.composite, &bind_groups[0]);
+        2 * views.len() as u32
+    }
+
+    fn pass(
+        &self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, clear: bool,
+        pipeline: &wgpu::RenderPipeline, source: &wgpu::BindGroup,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Bloom pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: if clear { wgpu::LoadOp::Clear(wgpu::Color::BLACK) } else { wgpu::LoadOp::Load },
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, source, &[]);
+        pass.set_bind_group(1, &self.params_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
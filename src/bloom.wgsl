@@ -0,0 +1,59 @@
+struct VertOut {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+struct Params {
+    threshold: f32,
+    intensity: f32,
+};
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(1) @binding(0)
+var<uniform> params: Params;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertOut {
+    var out: VertOut;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Averages four bilinear taps around `uv`, covering a 4x4 texel area of the source.
+fn box_blur(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    let offset = texel.xyxy * vec4<f32>(-1.0, -1.0, 1.0, 1.0);
+
+    return (
+        textureSample(source, source_sampler, uv + offset.xy).rgb +
+        textureSample(source, source_sampler, uv + offset.zy).rgb +
+        textureSample(source, source_sampler, uv + offset.xw).rgb +
+        textureSample(source, source_sampler, uv + offset.zw).rgb
+    ) * 0.25;
+}
+
+@fragment
+fn fs_prefilter(in: VertOut) -> @location(0) vec4<f32> {
+    let color = box_blur(in.uv);
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - params.threshold, 0.0) / max(brightness, 0.0001);
+
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+// Both down- and upsampling; upsampling blends additively onto the larger mip.
+@fragment
+fn fs_blur(in: VertOut) -> @location(0) vec4<f32> {
+    return vec4<f32>(box_blur(in.uv), 1.0);
+}
+
+@fragment
+fn fs_composite(in: VertOut) -> @location(0) vec4<f32> {
+    return vec4<f32>(box_blur(in.uv) * params.intensity, 0.0);
+}
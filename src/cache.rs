@@ -0,0 +1,39 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+};
+
+/// Keeps bind groups around across frames instead of recreating them every time they're bound. Entries have to be
+/// invalidated whenever a resource they refer to is replaced. [`State`](crate::State) keeps one for textures, and it
+/// works just as well for bind groups of your own.
+pub struct BindGroupCache<K> {
+    groups: HashMap<K, wgpu::BindGroup>,
+}
+
+impl<K: Hash + Eq> Default for BindGroupCache<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq> BindGroupCache<K> {
+    pub fn new() -> Self {
+        Self { groups: HashMap::new(), }
+    }
+
+    pub fn get_or_create(&mut self, key: K, create: impl FnOnce() -> wgpu::BindGroup) -> &wgpu::BindGroup {
+        self.groups.entry(key).or_insert_with(create)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&wgpu::BindGroup> {
+        self.groups.get(key)
+    }
+
+    pub fn invalidate(&mut self, key: &K) {
+        self.groups.remove(key);
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+    }
+}
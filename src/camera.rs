@@ -0,0 +1,311 @@
+use crate::{
+    resources::{
+        self,
+        Tracked,
+    },
+    upload::{
+        self,
+        Uploader,
+    },
+};
+use glam::{
+    Mat4, Vec3, Vec4,
+};
+
+/// A perspective camera looking from `eye` towards `target`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// Vertical field of view, in radians.
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl Camera {
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    /// Maps `znear` to depth 0 and `zfar` to depth 1, or the other way around if `reverse_z` is set.
+    pub fn projection(&self, aspect: f32, reverse_z: bool) -> Mat4 {
+        if reverse_z {
+            Mat4::perspective_rh(self.fovy, aspect, self.zfar, self.znear)
+        } else {
+            Mat4::perspective_rh(self.fovy, aspect, self.znear, self.zfar)
+        }
+    }
+
+    pub fn view_proj(&self, aspect: f32, reverse_z: bool) -> Mat4 {
+        self.projection(aspect, reverse_z) * self.view()
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            eye: Vec3::new(0.0, 0.0, 2.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            fovy: 45f32.to_radians(),
+            znear: 0.1,
+            zfar: 100.0,
+        }
+    }
+}
+
+/// An axis-aligned bounding box, in whatever space the transform it's culled with maps from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max, }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+}
+
+/// The six planes bounding what a view-projection sees, pointing inwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from the rows of `view_proj`, for wgpu's clip space where depth goes from 0 to 1. Holds
+    /// with reverse Z as well, which only swaps the near and far planes.
+    pub fn from_view_proj(view_proj: Mat4) -> Self {
+        let (x, y, z, w) = (view_proj.row(0), view_proj.row(1), view_proj.row(2), view_proj.row(3));
+        Self { planes: [w + x, w - x, w + y, w - y, z, w - z], }
+    }
+
+    /// Whether `aabb` transformed by `transform` may be in view. Tests the world-space box enclosing it against each
+    /// plane, so it errs towards keeping boxes near the frustum's edges, but never culls anything visible.
+    pub fn intersects(&self, transform: Mat4, aabb: Aabb) -> bool {
+        let center = transform.transform_point3(aabb.center());
+        let half = aabb.half_extents();
+        let extents = transform.x_axis.truncate().abs() * half.x
+            + transform.y_axis.truncate().abs() * half.y
+            + transform.z_axis.truncate().abs() * half.z;
+
+        // The planes aren't normalized, which doesn't matter as both sides scale with the normal.
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            normal.dot(center) + plane.w >= -normal.abs().dot(extents)
+        })
+    }
+}
+
+/// A sub-rectangle of the frame to render a camera's view into, in fractions of the frame's size so it holds at any
+/// resolution, and the depth range to map that view onto.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+}
+
+impl Default for Viewport {
+    /// The whole frame.
+    fn default() -> Self {
+        Self::new(0.0, 0.0, 1.0, 1.0)
+    }
+}
+
+impl Viewport {
+    /// A viewport covering the full depth range.
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self { x, y, width, height, min_depth: 0.0, max_depth: 1.0, }
+    }
+
+    /// Aspect ratio of this viewport on a `width` by `height` frame.
+    pub fn aspect(&self, width: u32, height: u32) -> f32 {
+        (self.width * width as f32) / (self.height * height as f32)
+    }
+
+    /// Sets the pass's viewport to this one on a `width` by `height` target, clamped to its bounds.
+    pub fn apply(&self, pass: &mut wgpu::RenderPass, width: u32, height: u32) {
+        let (width, height) = (width as f32, height as f32);
+        // wgpu rejects viewports that are empty or stick out of the target.
+        let x = (self.x * width).clamp(0.0, width - 1.0);
+        let y = (self.y * height).clamp(0.0, height - 1.0);
+        pass.set_viewport(
+            x, y,
+            (self.width * width).max(1.0).min(width - x), (self.height * height).max(1.0).min(height - y),
+            self.min_depth, self.max_depth,
+        );
+    }
+}
+
+/// A uniform buffer holding one view-projection per view, bound at binding 0 with a dynamic offset selecting the
+/// view, for anything drawn once from every view.
+pub struct ViewUniforms {
+    label: &'static str,
+    /// One view-projection per view, `stride` bytes apart.
+    buffer: Tracked<wgpu::Buffer>,
+    capacity: usize,
+    stride: wgpu::BufferAddress,
+    pub layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ViewUniforms {
+    pub fn new(device: &wgpu::Device, label: &'static str) -> Self {
+        let stride = upload::dynamic_stride::<Mat4>(device);
+        let buffer = Self::create_buffer(device, label, stride, 1);
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<Mat4>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let bind_group = Self::create_bind_group(device, label, &layout, &buffer);
+
+        Self { label, buffer, capacity: 1, stride, layout, bind_group, }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device, label: &str, stride: wgpu::BufferAddress, views: usize,
+    ) -> Tracked<wgpu::Buffer> {
+        resources::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some(label),
+            size: stride * views as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device, label: &str, layout: &wgpu::BindGroupLayout, buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<Mat4>() as u64),
+                }),
+            }],
+        })
+    }
+
+    /// Uploads the view-projection of every view, growing the buffer if they don't fit.
+    pub fn write(
+        &mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, uploader: &mut Uploader,
+        view_projs: &[Mat4],
+    ) {
+        if view_projs.len() > self.capacity {
+            self.capacity = view_projs.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.label, self.stride, self.capacity);
+            self.bind_group = Self::create_bind_group(device, self.label, &self.layout, &self.buffer);
+        }
+
+        for (i, view_proj) in view_projs.iter().enumerate() {
+            let offset = i as wgpu::BufferAddress * self.stride;
+            uploader.write(device, encoder, &self.buffer, offset, bytemuck::bytes_of(view_proj));
+        }
+    }
+
+    /// Binds the `view`th view-projection passed to [`ViewUniforms::write`] at `group`.
+    pub fn bind<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, group: u32, view: usize) {
+        pass.set_bind_group(group, &self.bind_group, &[(view as wgpu::BufferAddress * self.stride) as u32]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sees `x` and `y` in `[-1, 1]` and `z` in `[-10, 0]`.
+    fn ortho() -> Frustum {
+        Frustum::from_view_proj(Mat4::orthographic_rh(-1.0, 1.0, -1.0, 1.0, 0.0, 10.0))
+    }
+
+    fn cube(center: Vec3, half: f32) -> Aabb {
+        Aabb::new(center - Vec3::splat(half), center + Vec3::splat(half))
+    }
+
+    #[test]
+    fn inside() {
+        assert!(ortho().intersects(Mat4::IDENTITY, cube(Vec3::new(0.0, 0.0, -5.0), 0.5)));
+    }
+
+    #[test]
+    fn outside_each_plane() {
+        let frustum = ortho();
+        for center in [
+            Vec3::new(-3.0, 0.0, -5.0), Vec3::new(3.0, 0.0, -5.0),
+            Vec3::new(0.0, -3.0, -5.0), Vec3::new(0.0, 3.0, -5.0),
+            Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, -13.0),
+        ] {
+            assert!(!frustum.intersects(Mat4::IDENTITY, cube(center, 0.5)), "{center}");
+        }
+    }
+
+    #[test]
+    fn straddling_a_plane() {
+        assert!(ortho().intersects(Mat4::IDENTITY, cube(Vec3::new(1.0, 0.0, -5.0), 0.5)));
+    }
+
+    #[test]
+    fn touching_a_plane() {
+        let frustum = ortho();
+        assert!(frustum.intersects(Mat4::IDENTITY, cube(Vec3::new(1.5, 0.0, -5.0), 0.5)));
+        assert!(!frustum.intersects(Mat4::IDENTITY, cube(Vec3::new(1.51, 0.0, -5.0), 0.5)));
+    }
+
+    #[test]
+    fn empty_box() {
+        let frustum = ortho();
+        assert!(frustum.intersects(Mat4::IDENTITY, cube(Vec3::new(0.0, 0.0, -5.0), 0.0)));
+        assert!(!frustum.intersects(Mat4::IDENTITY, cube(Vec3::new(2.0, 0.0, -5.0), 0.0)));
+    }
+
+    #[test]
+    fn transformed() {
+        let frustum = ortho();
+        let aabb = cube(Vec3::ZERO, 0.5);
+        assert!(frustum.intersects(Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0)), aabb));
+        assert!(!frustum.intersects(Mat4::from_translation(Vec3::new(5.0, 0.0, -5.0)), aabb));
+        // Rotated by 45 degrees, the corners reach past where the unrotated box would end.
+        let rotated = Mat4::from_translation(Vec3::new(1.65, 0.0, -5.0)) * Mat4::from_rotation_z(45f32.to_radians());
+        assert!(frustum.intersects(rotated, aabb));
+        assert!(!frustum.intersects(Mat4::from_translation(Vec3::new(1.65, 0.0, -5.0)), aabb));
+    }
+
+    #[test]
+    fn reverse_z() {
+        let camera = Camera::default();
+        for reverse_z in [false, true] {
+            let frustum = Frustum::from_view_proj(camera.view_proj(1.0, reverse_z));
+            let visible = |z| frustum.intersects(Mat4::IDENTITY, cube(Vec3::new(0.0, 0.0, z), 0.5));
+            // At the target, behind the eye, and past the far plane.
+            assert_eq!([visible(0.0), visible(5.0), visible(-200.0)], [true, false, false], "reverse_z: {reverse_z}");
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    resources::{
+        self,
+        Tracked,
+    },
+    AndError,
+};
+use image::RgbaImage;
+use pollster::FutureExt as _;
+use std::{
+    fs,
+    future::Future,
+    num::NonZeroU32,
+    ops::RangeBounds,
+    path::Path,
+    pin::Pin,
+    sync::{
+        mpsc::{
+            self,
+            Sender,
+        },
+        Arc, Mutex,
+    },
+    task::{
+        Context, Poll, Waker,
+    },
+    thread,
+};
+
+pub type Swizzle = fn([u8; 4]) -> [u8; 4];
+
+/// How to turn a pixel of `format` into RGBA.
+pub fn swizzle(format: wgpu::TextureFormat) -> Result<Swizzle, AndError> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => Ok(|p| p),
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => Ok(|[b, g, r, a]| [r, g, b, a]),
+        format => Err(AndError::UnreadableFormat(format)),
+    }
+}
+
+#[derive(Default)]
+struct MappingState {
+    result: Option<Result<(), wgpu::BufferAsyncError>>,
+    waker: Option<Waker>,
+}
+
+/// Resolves once the `map_async` it was started with completes. On native, that needs the device to be polled; on
+/// the web, the browser completes it on its own.
+struct Mapping(Arc<Mutex<MappingState>>);
+
+impl Mapping {
+    fn read(slice: wgpu::BufferSlice) -> Self {
+        let state = Arc::new(Mutex::new(MappingState::default()));
+        let callback_state = state.clone();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let mut state = callback_state.lock().expect("Mapping state poisoned");
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        Self(state)
+    }
+}
+
+impl Future for Mapping {
+    type Output = Result<(), AndError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.0.lock().expect("Mapping state poisoned");
+        match state.result.take() {
+            Some(result) => Poll::Ready(result.map_err(AndError::Map)),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+/// Maps `range` of `buffer`, which needs `MAP_READ` usage and no pending writes by the CPU, copies it out and
+/// unmaps it again. The device is polled until the mapping is done on native; there's nothing to poll on the web,
+/// where awaiting this yields to the browser until it is.
+pub async fn read_buffer(
+    device: &wgpu::Device, buffer: &wgpu::Buffer, range: impl RangeBounds<wgpu::BufferAddress>,
+) -> Result<Vec<u8>, AndError> {
+    let slice = buffer.slice(range);
+    let mapping = Mapping::read(slice);
+    device.poll(wgpu::Maintain::Wait);
+    mapping.await?;
+
+    let data = slice.get_mapped_range().to_vec();
+    buffer.unmap();
+    Ok(data)
+}
+
+/// A region of a 4-bytes-per-pixel texture copied into a mappable buffer, with rows padded to the copy alignment.
+pub struct Readback {
+    pub buffer: Tracked<wgpu::Buffer>,
+    pub width: u32,
+    pub height: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl Readback {
+    /// Encodes the copy of the `width` by `height` region at `(x, y)`; the data is there once `encoder` is submitted.
+    pub fn new(
+        device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture,
+        (x, y): (u32, u32), width: u32, height: u32,
+    ) -> Self {
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (width * 4).div_ceil(align) * align;
+
+        let buffer = resources::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some("Readback"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0, },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1, },
+        );
+
+        Self { buffer, width, height, padded_bytes_per_row, }
+    }
+
+    /// Reads the buffer through [`read_buffer`], blocking until the copy is done, and strips the row padding off,
+    /// yielding tightly packed RGBA.
+    pub fn read(&self, device: &wgpu::Device, swizzle: Swizzle) -> Result<Vec<u8>, AndError> {
+        let data = read_buffer(device, &self.buffer, ..).block_on()?;
+        let mut pixels = Vec::with_capacity((self.width * self.height * 4) as usize);
+        for row in data.chunks_exact(self.padded_bytes_per_row as usize) {
+            for pixel in row[..(self.width * 4) as usize].chunks_exact(4) {
+                pixels.extend_from_slice(&swizzle([pixel[0], pixel[1], pixel[2], pixel[3]]));
+            }
+        }
+
+        Ok(pixels)
+    }
+
+    pub fn read_image(&self, device: &wgpu::Device, swizzle: Swizzle) -> Result<RgbaImage, AndError> {
+        Ok(RgbaImage::from_raw(self.width, self.height, self.read(device, swizzle)?).expect("Readback size mismatch"))
+    }
+}
+
+/// Writes a number of consecutive frames to `frameNNNN.png` files. Frames are read back and encoded to PNG on a
+/// separate thread, in order, so waiting on their copies never blocks the frame loop.
+pub struct Recorder {
+    remaining: u32,
+    next_index: u32,
+    writer: Sender<(u32, Readback)>,
+}
+
+impl Recorder {
+    pub fn new(device: Arc<wgpu::Device>, dir: &Path, frame_count: u32, swizzle: Swizzle) -> Result<Self, AndError> {
+        fs::create_dir_all(dir)?;
+
+        let dir = dir.to_path_buf();
+        let (writer, frames) = mpsc::channel::<(u32, Readback)>();
+        thread::Builder::new()
+            .name("And frame recorder".to_string())
+            .spawn(move || {
+                for (index, readback) in frames {
+                    let path = dir.join(format!("frame{index:04}.png"));
+                    match readback.read_image(&device, swizzle) {
+                        Ok(img) => if let Err(e) = img.save(&path) {
+                            log::error!("Couldn't write {}: {e}", path.display());
+                        },
+                        Err(e) => log::error!("Couldn't map recorded frame {index}: {e}"),
+                    }
+                }
+            })
+            .expect("Couldn't spawn frame recorder thread");
+
+        Ok(Self { remaining: frame_count, next_index: 0, writer, })
+    }
+
+    pub fn wants_frame(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Whether every frame was taken. The writer thread finishes whatever is left of them even after the recorder
+    /// is dropped.
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Takes a frame whose copy has just been submitted.
+    pub fn push(&mut self, readback: Readback) {
+        drop(self.writer.send((self.next_index, readback)));
+        self.next_index += 1;
+        self.remaining -= 1;
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{
+    loader::{
+        self,
+        Texture,
+    },
+    resources,
+    AndError,
+};
+use ktx2::Format;
+use std::path::Path;
+
+/// Device features covering every format [`load_ktx2`] uploads without decompressing.
+pub const FEATURES: wgpu::Features = wgpu::Features::TEXTURE_COMPRESSION_BC
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ETC2)
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ASTC_LDR);
+
+type Decoder = fn(&[u8], usize, usize, &mut [u32]) -> Result<(), &'static str>;
+
+/// A block-compressed format KTX2 files may come in, and how to decompress it where the device can't sample it.
+struct Compressed {
+    format: wgpu::TextureFormat,
+    feature: wgpu::Features,
+    block_bytes: usize,
+    srgb: bool,
+    decode: Decoder,
+}
+
+/// All supported formats use 4x4 blocks.
+const BLOCK_SIZE: u32 = 4;
+
+fn compressed(format: Format) -> Option<Compressed> {
+    use texture2ddecoder::{
+        decode_bc1, decode_bc1a, decode_bc3, decode_bc7,
+        decode_etc2_rgb, decode_etc2_rgba8,
+    };
+    use wgpu::{
+        AstcBlock, AstcChannel,
+        TextureFormat as Tf,
+    };
+    const BC: wgpu::Features = wgpu::Features::TEXTURE_COMPRESSION_BC;
+    const ETC2: wgpu::Features = wgpu::Features::TEXTURE_COMPRESSION_ETC2;
+    const ASTC: wgpu::Features = wgpu::Features::TEXTURE_COMPRESSION_ASTC_LDR;
+    let astc_4x4 = |channel| Tf::Astc { block: AstcBlock::B4x4, channel, };
+
+    let (format, feature, block_bytes, decode): (_, _, _, Decoder) = match format {
+        Format::BC1_RGB_UNORM_BLOCK => (Tf::Bc1RgbaUnorm, BC, 8, decode_bc1),
+        Format::BC1_RGB_SRGB_BLOCK => (Tf::Bc1RgbaUnormSrgb, BC, 8, decode_bc1),
+        Format::BC1_RGBA_UNORM_BLOCK => (Tf::Bc1RgbaUnorm, BC, 8, decode_bc1a),
+        Format::BC1_RGBA_SRGB_BLOCK => (Tf::Bc1RgbaUnormSrgb, BC, 8, decode_bc1a),
+        Format::BC3_UNORM_BLOCK => (Tf::Bc3RgbaUnorm, BC, 16, decode_bc3),
+        Format::BC3_SRGB_BLOCK => (Tf::Bc3RgbaUnormSrgb, BC, 16, decode_bc3),
+        Format::BC7_UNORM_BLOCK => (Tf::Bc7RgbaUnorm, BC, 16, decode_bc7),
+        Format::BC7_SRGB_BLOCK => (Tf::Bc7RgbaUnormSrgb, BC, 16, decode_bc7),
+        Format::ETC2_R8G8B8_UNORM_BLOCK => (Tf::Etc2Rgb8Unorm, ETC2, 8, decode_etc2_rgb),
+        Format::ETC2_R8G8B8_SRGB_BLOCK => (Tf::Etc2Rgb8UnormSrgb, ETC2, 8, decode_etc2_rgb),
+        Format::ETC2_R8G8B8A8_UNORM_BLOCK => (Tf::Etc2Rgba8Unorm, ETC2, 16, decode_etc2_rgba8),
+        Format::ETC2_R8G8B8A8_SRGB_BLOCK => (Tf::Etc2Rgba8UnormSrgb, ETC2, 16, decode_etc2_rgba8),
+        Format::ASTC_4x4_UNORM_BLOCK => (astc_4x4(AstcChannel::Unorm), ASTC, 16, decode_astc_4x4),
+        Format::ASTC_4x4_SRGB_BLOCK => (astc_4x4(AstcChannel::UnormSrgb), ASTC, 16, decode_astc_4x4),
+        _ => return None,
+    };
+
+    Some(Compressed { format, feature, block_bytes, srgb: format.describe().srgb, decode, })
+}
+
+fn decode_astc_4x4(data: &[u8], width: usize, height: usize, image: &mut [u32]) -> Result<(), &'static str> {
+    texture2ddecoder::decode_astc(data, width, height, BLOCK_SIZE as usize, BLOCK_SIZE as usize, image)
+}
+
+/// Loads a single-layer 2D KTX2 texture in one of the BC1/3/7, ETC2 or ASTC 4x4 formats, uploading it as is with
+/// all its mip levels if the device supports the format. Otherwise only the base level is decompressed to RGBA8.
+pub fn load_ktx2(device: &wgpu::Device, queue: &wgpu::Queue, path: &Path) -> Result<Texture, AndError> {
+    let bytes = std::fs::read(path)?;
+    let reader = ktx2::Reader::new(bytes.as_slice())?;
+    let header = reader.header();
+    let unsupported = |reason: String| Err(AndError::UnsupportedKtx2(reason));
+
+    if header.supercompression_scheme.is_some() {
+        return unsupported(format!("supercompression {:?}", header.supercompression_scheme));
+    }
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+        return unsupported("only single-layer 2D textures are supported".to_string());
+    }
+
+    let Some(compressed) = header.format.and_then(compressed) else {
+        return unsupported(format!("format {:?}", header.format));
+    };
+
+    let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+    if width % BLOCK_SIZE != 0 || height % BLOCK_SIZE != 0 {
+        return unsupported(format!("{width}x{height} isn't a multiple of the {BLOCK_SIZE}x{BLOCK_SIZE} block size"));
+    }
+
+    let level_bytes = |level: u32| {
+        let blocks = |size: u32| (size >> level).max(1).div_ceil(BLOCK_SIZE) as usize;
+        blocks(width) * blocks(height) * compressed.block_bytes
+    };
+    let levels = reader.levels().map(|level| level.data).collect::<Vec<_>>();
+    if let Some(level) = (0..levels.len()).find(|&i| levels[i].len() < level_bytes(i as u32)) {
+        return unsupported(format!("mip level {level} is truncated"));
+    }
+
+    let label = path.to_str();
+    if device.features().contains(compressed.feature) {
+        let data = levels.iter().enumerate()
+            .flat_map(|(i, level)| &level[..level_bytes(i as u32)])
+            .copied()
+            .collect::<Vec<_>>();
+        let texture = resources::create_texture_with_data(device, queue, &wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1, },
+            mip_level_count: levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: compressed.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        }, &data);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        return Ok(Texture { texture, view, width, height, premultiplied: false, });
+    }
+
+    log::info!("{:?} isn't supported, decompressing {}", compressed.format, path.display());
+    let mut pixels = vec![0u32; width as usize * height as usize];
+    (compressed.decode)(levels[0], width as usize, height as usize, &mut pixels)
+        .map_err(|e| AndError::UnsupportedKtx2(e.to_string()))?;
+
+    // Decoded texels are BGRA.
+    let rgba = pixels.iter().flat_map(|texel| {
+        let [b, g, r, a] = texel.to_le_bytes();
+        [r, g, b, a]
+    }).collect::<Vec<_>>();
+    let format = if compressed.srgb {
+        wgpu::TextureFormat::Rgba8UnormSrgb
+    } else {
+        wgpu::TextureFormat::Rgba8Unorm
+    };
+    Ok(loader::create_with_format(device, queue, label, format, width, height, &rgba))
+}
@@ -0,0 +1,193 @@
+use crate::{
+    camera::ViewUniforms,
+    resources::{
+        self,
+        Tracked,
+    },
+    upload::Uploader,
+};
+use bytemuck::{
+    Pod, Zeroable,
+};
+use glam::{
+    Mat4, Vec3,
+};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+impl LineVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+
+    fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Immediate-mode line renderer for debug geometry. Lines are collected throughout the frame, drawn on top of the
+/// scene from every view, then forgotten.
+pub struct DebugLines {
+    pub depth_test: bool,
+    vertices: Vec<LineVertex>,
+    buffer: Tracked<wgpu::Buffer>,
+    capacity: usize,
+    views: ViewUniforms,
+    tested: wgpu::RenderPipeline,
+    overlay: wgpu::RenderPipeline,
+}
+
+impl DebugLines {
+    const INITIAL_CAPACITY: usize = 1024;
+    /// Most lines drawn on either side of a grid's center along each axis, so a tiny spacing can't queue millions.
+    const MAX_GRID_LINES: i32 = 512;
+
+    pub fn new(
+        device: &wgpu::Device, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, sample_count: u32,
+        reverse_z: bool,
+    ) -> Self {
+        let views = ViewUniforms::new(device, "Debug lines uniform");
+        let shader = device.create_shader_module(wgpu::include_wgsl!("debug.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug lines pipeline layout"),
+            bind_group_layouts: &[&views.layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |depth_compare| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Debug lines pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[LineVertex::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+        });
+
+        let tested = pipeline(if reverse_z {
+            wgpu::CompareFunction::GreaterEqual
+        } else {
+            wgpu::CompareFunction::LessEqual
+        });
+        let overlay = pipeline(wgpu::CompareFunction::Always);
+
+        Self {
+            depth_test: true,
+            vertices: Vec::new(),
+            buffer: Self::create_buffer(device, Self::INITIAL_CAPACITY),
+            capacity: Self::INITIAL_CAPACITY,
+            views, tested, overlay,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> Tracked<wgpu::Buffer> {
+        resources::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some("Debug lines vertices"),
+            size: (capacity * std::mem::size_of::<LineVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn line(&mut self, a: Vec3, b: Vec3, color: [f32; 4]) {
+        self.vertices.push(LineVertex { position: a.into(), color, });
+        self.vertices.push(LineVertex { position: b.into(), color, });
+    }
+
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: [f32; 4]) {
+        let corner = |i: usize| Vec3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        );
+
+        // Every pair of corners differing in exactly one axis is an edge.
+        for i in 0..8 {
+            for axis in [1, 2, 4] {
+                if i & axis == 0 {
+                    self.line(corner(i), corner(i | axis), color);
+                }
+            }
+        }
+    }
+
+    /// Draws nothing unless `spacing` is finite and positive, and at most [`Self::MAX_GRID_LINES`] lines on either
+    /// side of `center`.
+    pub fn grid(&mut self, center: Vec3, half_extent: f32, spacing: f32, color: [f32; 4]) {
+        if !spacing.is_finite() || spacing <= 0.0 { return };
+
+        let count = ((half_extent / spacing).floor() as i32).clamp(0, Self::MAX_GRID_LINES);
+        for i in -count..=count {
+            let offset = i as f32 * spacing;
+            self.line(
+                center + Vec3::new(offset, 0.0, -half_extent),
+                center + Vec3::new(offset, 0.0, half_extent),
+                color,
+            );
+            self.line(
+                center + Vec3::new(-half_extent, 0.0, offset),
+                center + Vec3::new(half_extent, 0.0, offset),
+                color,
+            );
+        }
+    }
+
+    /// Uploads this frame's lines and the view-projection of every view they're drawn from, growing the buffers if
+    /// they don't fit.
+    pub fn prepare(
+        &mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, uploader: &mut Uploader,
+        view_projs: &[Mat4],
+    ) {
+        if self.vertices.is_empty() { return };
+
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+
+        self.views.write(device, encoder, uploader, view_projs);
+        uploader.write(device, encoder, &self.buffer, 0, bytemuck::cast_slice(&self.vertices));
+    }
+
+    /// Draws the lines as seen from the `view`th view passed to [`DebugLines::prepare`].
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, view: usize) {
+        if self.vertices.is_empty() { return };
+
+        pass.set_pipeline(if self.depth_test { &self.tested } else { &self.overlay });
+        self.views.bind(pass, 0, view);
+        pass.set_vertex_buffer(0, self.buffer.slice(..));
+        pass.draw(0..self.vertices.len() as u32, 0..1);
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{
+    Config, Sprite, SpriteSortMode,
+};
+use glam::Vec2;
+
+/// SplitMix64. Defined here rather than pulled from a crate, so the sequence for a given seed can never change
+/// under a dependency update.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`, with exactly representable steps.
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// A [`Config`] drawing the same opaque colored quads in the same order every frame, placed from `seed`. Rendering it
+/// at a fixed size and reading it back with [`State::capture_frame`](crate::State::capture_frame) gives
+/// bit-identical images on the same GPU and driver.
+pub fn demo_scene(seed: u64) -> Config {
+    const QUADS: usize = 64;
+
+    // Everything random is decided up front, in fractions of the frame size.
+    let mut rng = Rng(seed);
+    let quads = (0..QUADS)
+        .map(|_| {
+            let size = Vec2::new(0.05 + rng.next_f32() * 0.2, 0.05 + rng.next_f32() * 0.2);
+            let position = Vec2::new(rng.next_f32(), rng.next_f32()) * (Vec2::ONE - size);
+            let color = [rng.next_f32(), rng.next_f32(), rng.next_f32(), 1.0];
+            (position, size, color)
+        })
+        .collect::<Vec<_>>();
+
+    let mut white = None;
+    Config {
+        on_frame: Some(Box::new(move |state| {
+            let texture = *white.get_or_insert_with(|| state.create_texture(1, 1, &[255; 4]));
+            let size = state.size();
+            let frame = Vec2::new(size.width as f32, size.height as f32);
+
+            state.set_sprite_sort_mode(SpriteSortMode::Deferred);
+            for &(position, size, color) in &quads {
+                state.draw_sprite(Sprite { color, ..Sprite::new(texture, position * frame, size * frame) });
+            }
+        })),
+        ..Config::default()
+    }
+}
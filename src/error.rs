@@ -0,0 +1,61 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AndError {
+    #[error("No adapter can present to the window")]
+    NoAdapter,
+    #[error("Couldn't request device: {0}")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+    #[error("Pixel ({x}, {y}) is outside of the {width}x{height} frame")]
+    OutOfBounds { x: u32, y: u32, width: u32, height: u32, },
+    #[error("Surface format {format:?} isn't supported, pick one of {supported:?}")]
+    UnsupportedFormat { format: wgpu::TextureFormat, supported: Vec<wgpu::TextureFormat>, },
+    #[error("Address mode {0:?} isn't supported, pick ClampToEdge, Repeat or MirrorRepeat")]
+    UnsupportedAddressMode(wgpu::AddressMode),
+    #[error("{samples}x MSAA isn't supported, pick one of {supported:?}")]
+    UnsupportedSampleCount { samples: u32, supported: Vec<u32>, },
+    #[error("Can't read back pixels of format {0:?}")]
+    UnreadableFormat(wgpu::TextureFormat),
+    #[error("Couldn't map buffer: {0}")]
+    Map(wgpu::BufferAsyncError),
+    #[error("Depth clear value {0} is outside of [0, 1]")]
+    InvalidDepthClear(f32),
+    #[error("Invalid mesh: {0}")]
+    InvalidMesh(String),
+    #[error("Invalid image: {0}")]
+    InvalidImage(String),
+    #[error("Invalid uniform: {0}")]
+    InvalidUniform(String),
+    #[error("Texture loading panicked: {0}")]
+    LoaderPanic(String),
+    #[error("The texture loader thread has stopped")]
+    LoaderStopped,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Couldn't load image: {0}")]
+    Image(#[from] image::ImageError),
+    #[cfg(feature = "audio")]
+    #[error("Couldn't open audio output: {0}")]
+    AudioStream(#[from] rodio::StreamError),
+    #[cfg(feature = "audio")]
+    #[error("Couldn't decode sound: {0}")]
+    AudioDecode(#[from] rodio::decoder::DecoderError),
+    #[cfg(feature = "audio")]
+    #[error("Couldn't play sound: {0}")]
+    AudioPlay(#[from] rodio::PlayError),
+    #[cfg(feature = "settings")]
+    #[error("Couldn't parse settings: {0}")]
+    SettingsParse(#[from] toml::de::Error),
+    #[cfg(feature = "settings")]
+    #[error("Couldn't serialize settings: {0}")]
+    SettingsWrite(#[from] toml::ser::Error),
+    #[cfg(feature = "obj")]
+    #[error("Couldn't load OBJ file: {0}")]
+    Obj(#[from] tobj::LoadError),
+    #[cfg(feature = "ktx2")]
+    #[error("Couldn't parse KTX2 file: {0}")]
+    Ktx2(#[from] ktx2::ParseError),
+    #[cfg(feature = "ktx2")]
+    #[error("Unsupported KTX2 file: {0}")]
+    UnsupportedKtx2(String),
+}
@@ -0,0 +1,127 @@
+//! Tests rendering real frames on a hidden window, so they need a GPU and a display. They're ignored by default; run
+//! them with `cargo test -- --ignored` on a machine that has both.
+
+use super::*;
+use winit::event_loop::EventLoopBuilder;
+
+struct Harness {
+    state: State,
+    // Dropped last, as the window and surface belong to it.
+    _event_loop: EventLoop<UserEvent>,
+}
+
+impl Harness {
+    /// Fails the test if there's no adapter, which is why every test using this is ignored by default.
+    fn new(config: Config, width: u32, height: u32) -> Self {
+        let mut builder = EventLoopBuilder::with_user_event();
+        // The test harness runs each test on its own thread.
+        #[cfg(any(
+            target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd",
+            target_os = "openbsd",
+        ))]
+        winit::platform::unix::EventLoopBuilderExtUnix::with_any_thread(&mut builder, true);
+        #[cfg(target_os = "windows")]
+        winit::platform::windows::EventLoopBuilderExtWindows::with_any_thread(&mut builder, true);
+        let event_loop = builder.build();
+
+        let window = Arc::new(WindowBuilder::new()
+            .with_visible(false)
+            .with_transparent(config.transparent)
+            .with_inner_size(PhysicalSize::new(width, height))
+            .build(&event_loop)
+            .expect("Couldn't create window"));
+        let gpu = Gpu::new(&window, &config).expect("Couldn't set up the GPU");
+        let state = State::with_gpu(window, event_loop.create_proxy(), config, gpu);
+        Self { state, _event_loop: event_loop, }
+    }
+
+    /// Renders a frame, failing the test if it doesn't pass validation.
+    fn render(&mut self) {
+        self.state.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        self.state.render().expect("Couldn't render a frame");
+        if let Some(error) = self.state.device.pop_error_scope().block_on() {
+            panic!("Frame failed validation: {error}");
+        }
+    }
+}
+
+/// Renders a half-transparent red window and checks that its alpha survives into the frame. The frame is also written
+/// to `half_transparent.png` in the temporary directory, to look over by eye.
+#[test]
+#[ignore = "needs a GPU and a display"]
+fn half_transparent_clear() {
+    let config = Config {
+        transparent: true,
+        clear_color: wgpu::Color { r: 1.0, g: 0.0, b: 0.0, a: 0.5, },
+        ..Config::default()
+    };
+    let mut harness = Harness::new(config, 64, 64);
+    harness.render();
+
+    // A corner, away from the placeholder triangle.
+    let [r, g, b, a] = harness.state.read_pixel(0, 0).expect("Readable frame");
+    assert!((127..=128).contains(&a), "alpha {a} isn't half");
+    assert_eq!((g, b), (0, 0));
+    // Straight, or premultiplied and possibly sRGB-encoded on top, but never darker than the alpha would make it.
+    assert!(r >= a, "red {r} is below alpha {a}");
+
+    let path = std::env::temp_dir().join("half_transparent.png");
+    harness.state.capture_frame().expect("Readable frame").save(&path).expect("Couldn't write frame");
+}
+
+/// Every size-dependent texture has to be replaced on resize rather than kept around next to its successor,
+/// multisampled attachments included.
+#[test]
+#[ignore = "needs a GPU and a display"]
+fn resizing_keeps_texture_count() {
+    let config = Config { bloom: Some(Default::default()), msaa_samples: 4, ..Config::default() };
+    let mut harness = Harness::new(config, 256, 256);
+    harness.render();
+    let before = harness.state.resource_usage();
+
+    for i in 0..100 {
+        harness.state.resize(128 + i * 7 % 300, 96 + i * 13 % 200);
+        harness.render();
+        assert_eq!(harness.state.resource_usage().textures, before.textures, "after resize {i}");
+    }
+
+    harness.state.resize(256, 256);
+    harness.render();
+    let after = harness.state.resource_usage();
+    assert_eq!((after.textures, after.texture_bytes), (before.textures, before.texture_bytes));
+}
+
+/// Switching present modes should only reconfigure the surface, leaving every texture and buffer the crate holds
+/// in place, and the frame right after should render fine.
+#[test]
+#[ignore = "needs a GPU and a display"]
+fn present_mode_change_keeps_resources() {
+    let mut harness = Harness::new(Config::default(), 128, 128);
+    harness.render();
+    let before = harness.state.resource_usage();
+    let pixel = harness.state.read_pixel(64, 64).expect("Readable frame");
+
+    for mode in [wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox, wgpu::PresentMode::Fifo] {
+        harness.state.set_present_mode(mode);
+        assert_eq!(harness.state.resource_usage(), before, "after switching to {mode:?}");
+
+        harness.render();
+        assert_eq!(harness.state.read_pixel(64, 64).expect("Readable frame"), pixel, "rendered with {mode:?}");
+    }
+}
+
+/// Going from no MSAA to 4x and back should render valid frames each time, and leave the resources as they were.
+#[test]
+#[ignore = "needs a GPU and a display"]
+fn toggle_msaa() {
+    let mut harness = Harness::new(Config::default(), 128, 128);
+    harness.render();
+    let before = harness.state.resource_usage();
+
+    for samples in [4, 1] {
+        harness.state.set_msaa(samples).expect("Supported sample count");
+        assert_eq!(harness.state.config().msaa_samples, samples);
+        harness.render();
+    }
+    assert_eq!(harness.state.resource_usage(), before);
+}
@@ -0,0 +1,98 @@
+use crate::resources::{
+    self,
+    Tracked,
+};
+use std::sync::{
+    Arc, Mutex,
+};
+
+/// Byte size of the two resolved timestamps.
+const SIZE: wgpu::BufferAddress = 2 * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+
+/// Measures how long the GPU takes on a frame's commands, from a timestamp written before them to one written after.
+/// Timestamps are read back without stalling: a frame is only timed once the previous readback is done, so the
+/// reported time lags a frame or two behind. Needs [`wgpu::Features::TIMESTAMP_QUERY`].
+pub struct GpuTimer {
+    queries: wgpu::QuerySet,
+    readback: Tracked<wgpu::Buffer>,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Whether this frame's commands are being timed.
+    timing: bool,
+    /// Set while `readback` is being resolved into or mapped, until the latter finishes.
+    mapping: Option<Arc<Mutex<Option<bool>>>>,
+    last: Option<f32>,
+}
+
+impl GpuTimer {
+    /// `None` if the device doesn't have timestamp queries enabled.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) { return None };
+
+        let queries = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("GPU timer queries"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let readback = resources::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some("GPU timer readback"),
+            size: SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            queries, readback,
+            period: queue.get_timestamp_period(),
+            timing: false,
+            mapping: None,
+            last: None,
+        })
+    }
+
+    /// Picks up a finished readback, then starts timing the commands recorded into `encoder` from here on, unless
+    /// the readback is still pending.
+    pub fn begin(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        // Mapping callbacks only run when the device is polled on native.
+        device.poll(wgpu::Maintain::Poll);
+        if let Some(mapping) = &self.mapping {
+            let Some(mapped) = *mapping.lock().expect("GPU timer mapping poisoned") else { return };
+            if mapped {
+                let data = self.readback.slice(..).get_mapped_range();
+                let [start, end]: [u64; 2] = bytemuck::pod_read_unaligned(&data);
+                drop(data);
+                self.readback.unmap();
+                self.last = Some(end.saturating_sub(start) as f32 * self.period / 1_000_000.0);
+            }
+            self.mapping = None;
+        }
+
+        encoder.write_timestamp(&self.queries, 0);
+        self.timing = true;
+    }
+
+    /// Stops timing, resolving both timestamps into the readback buffer.
+    pub fn end(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.timing { return };
+
+        encoder.write_timestamp(&self.queries, 1);
+        encoder.resolve_query_set(&self.queries, 0..2, &self.readback, 0);
+    }
+
+    /// Starts reading the timestamps back. Call after submitting the encoder [`GpuTimer::end`] was called with.
+    pub fn submitted(&mut self) {
+        if !std::mem::take(&mut self.timing) { return };
+
+        let mapping = Arc::new(Mutex::new(None));
+        let callback_mapping = mapping.clone();
+        self.readback.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            *callback_mapping.lock().expect("GPU timer mapping poisoned") = Some(result.is_ok());
+        });
+        self.mapping = Some(mapping);
+    }
+
+    /// GPU time of the last frame that was timed and read back, in milliseconds.
+    pub fn last(&self) -> Option<f32> {
+        self.last
+    }
+}
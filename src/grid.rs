@@ -0,0 +1,182 @@
+use crate::{
+    resources::{
+        self,
+        Tracked,
+    },
+    upload::{
+        self,
+        Uploader,
+    },
+};
+use bytemuck::{
+    Pod, Zeroable,
+};
+use glam::{
+    Mat4, Vec3,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridSettings {
+    /// Distance between lines in world units.
+    pub spacing: f32,
+    /// Straight, not premultiplied, alpha.
+    pub color: [f32; 4],
+    /// Distance from the camera at which the grid has faded out entirely.
+    pub fade_distance: f32,
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self { spacing: 1.0, color: [0.5, 0.5, 0.5, 0.8], fade_distance: 50.0, }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct GridUniform {
+    view_proj: [[f32; 4]; 4],
+    inv_view_proj: [[f32; 4]; 4],
+    color: [f32; 4],
+    eye: [f32; 3],
+    spacing: f32,
+    fade_distance: f32,
+    _pad: [f32; 3],
+}
+
+/// An infinite ground grid on the XZ plane through the origin, ray-cast per pixel from a fullscreen triangle. Lines
+/// stay a pixel wide at any distance and fade out towards [`GridSettings::fade_distance`]. The grid writes its own
+/// depth so it's hidden behind the scene, but doesn't write to the depth buffer.
+pub struct Grid {
+    pub settings: GridSettings,
+    /// One uniform per view, `stride` bytes apart.
+    uniform: Tracked<wgpu::Buffer>,
+    capacity: usize,
+    stride: wgpu::BufferAddress,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Grid {
+    pub fn new(
+        device: &wgpu::Device, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, sample_count: u32,
+        reverse_z: bool, settings: GridSettings,
+    ) -> Self {
+        let stride = upload::dynamic_stride::<GridUniform>(device);
+        let uniform = Self::create_uniform(device, stride, 1);
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Grid bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<GridUniform>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let bind_group = Self::create_bind_group(device, &layout, &uniform);
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("grid.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Grid pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Grid pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: if reverse_z {
+                    wgpu::CompareFunction::GreaterEqual
+                } else {
+                    wgpu::CompareFunction::LessEqual
+                },
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+        });
+
+        Self { settings, uniform, capacity: 1, stride, layout, bind_group, pipeline, }
+    }
+
+    fn create_uniform(device: &wgpu::Device, stride: wgpu::BufferAddress, views: usize) -> Tracked<wgpu::Buffer> {
+        resources::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some("Grid uniform"),
+            size: stride * views as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device, layout: &wgpu::BindGroupLayout, uniform: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Grid bind group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: uniform,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<GridUniform>() as u64),
+                }),
+            }],
+        })
+    }
+
+    /// Uploads the view-projection and camera position of every view the grid is drawn from.
+    pub fn prepare(
+        &mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, uploader: &mut Uploader,
+        views: &[(Mat4, Vec3)],
+    ) {
+        if views.len() > self.capacity {
+            self.capacity = views.len().next_power_of_two();
+            self.uniform = Self::create_uniform(device, self.stride, self.capacity);
+            self.bind_group = Self::create_bind_group(device, &self.layout, &self.uniform);
+        }
+
+        for (i, &(view_proj, eye)) in views.iter().enumerate() {
+            let uniform = GridUniform {
+                view_proj: view_proj.to_cols_array_2d(),
+                inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+                color: self.settings.color,
+                eye: eye.into(),
+                spacing: self.settings.spacing.max(f32::EPSILON),
+                fade_distance: self.settings.fade_distance,
+                _pad: [0.0; 3],
+            };
+            let offset = i as wgpu::BufferAddress * self.stride;
+            uploader.write(device, encoder, &self.uniform, offset, bytemuck::bytes_of(&uniform));
+        }
+    }
+
+    /// Draws the grid as seen from the `view`th view passed to [`Grid::prepare`].
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, view: usize) {
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[(view as wgpu::BufferAddress * self.stride) as u32]);
+        pass.draw(0..3, 0..1);
+    }
+}
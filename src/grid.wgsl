@@ -0,0 +1,59 @@
+struct View {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    color: vec4<f32>,
+    eye: vec3<f32>,
+    spacing: f32,
+    fade_distance: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> view: View;
+
+struct VertOut {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+struct FragOut {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertOut {
+    var out: VertOut;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertOut) -> FragOut {
+    // Any depth works for a point on the pixel's ray, whichever way around depth goes.
+    let on_ray = view.inv_view_proj * vec4<f32>(in.ndc, 0.5, 1.0);
+    let ray = on_ray.xyz / on_ray.w - view.eye;
+    let t = -view.eye.y / ray.y;
+    let hit = view.eye + ray * t;
+
+    // Distance to the nearest line in pixels, for lines a pixel wide at any distance.
+    let coord = hit.xz / view.spacing;
+    let width = fwidth(coord);
+    let lines = abs(fract(coord - 0.5) - 0.5) / width;
+    let coverage = 1.0 - min(min(lines.x, lines.y), 1.0);
+    let fade = 1.0 - smoothstep(0.0, view.fade_distance, distance(hit, view.eye));
+
+    // Derivatives above have to be taken before anything is discarded.
+    if (t <= 0.0 || coverage * fade <= 0.0) {
+        discard;
+    }
+
+    let clip = view.view_proj * vec4<f32>(hit, 1.0);
+    var out: FragOut;
+    out.color = vec4<f32>(view.color.rgb, view.color.a * coverage * fade);
+    out.depth = clip.z / clip.w;
+    return out;
+}
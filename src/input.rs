@@ -0,0 +1,74 @@
+use std::collections::HashSet;
+use winit::event::{
+    ElementState, KeyboardInput, ModifiersState, VirtualKeyCode,
+};
+
+/// Input state accumulated from window events.
+#[derive(Debug, Default, Clone)]
+pub struct Input {
+    pub modifiers: ModifiersState,
+    /// Raw mouse motion since the last frame, unaffected by pointer acceleration and still reported when the cursor
+    /// is grabbed or at the edge of the screen, as needed for mouse-look. Units are device-specific.
+    pub mouse_delta: glam::Vec2,
+    /// Where the cursor is over the window, in physical pixels from the top-left of [`crate::State::content_rect`],
+    /// so it lines up with sprites and shapes. Outside of that in the bars around letterboxed content, and `None`
+    /// while the cursor is off the window.
+    pub cursor_position: Option<glam::Vec2>,
+    /// By scancode, since not every key maps to a [`VirtualKeyCode`] and repeats have to be caught for all of them.
+    held_scancodes: HashSet<u32>,
+    held: HashSet<VirtualKeyCode>,
+    pressed: HashSet<VirtualKeyCode>,
+    released: HashSet<VirtualKeyCode>,
+}
+
+impl Input {
+    /// Whether `key` is down, however long it has been.
+    pub fn is_held(&self, key: VirtualKeyCode) -> bool {
+        self.held.contains(&key)
+    }
+
+    /// Whether `key` went down since the last frame. OS key repeats while it's held don't count.
+    pub fn just_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    /// Whether `key` went up since the last frame.
+    pub fn just_released(&self, key: VirtualKeyCode) -> bool {
+        self.released.contains(&key)
+    }
+
+    /// Records a key event, returning `false` if it's an OS repeat of a key that's already held.
+    pub(crate) fn key(&mut self, input: &KeyboardInput) -> bool {
+        match input.state {
+            ElementState::Pressed => {
+                if !self.held_scancodes.insert(input.scancode) { return false };
+                if let Some(key) = input.virtual_keycode {
+                    self.held.insert(key);
+                    self.pressed.insert(key);
+                }
+            },
+            ElementState::Released => {
+                self.held_scancodes.remove(&input.scancode);
+                if let Some(key) = input.virtual_keycode {
+                    self.held.remove(&key);
+                    self.released.insert(key);
+                }
+            },
+        }
+
+        true
+    }
+
+    /// Lets go of every held key, since their releases won't be reported while the window is unfocused.
+    pub(crate) fn release_all(&mut self) {
+        self.released.extend(self.held.drain());
+        self.held_scancodes.clear();
+    }
+
+    /// Clears everything that's only meant to last a frame.
+    pub(crate) fn end_frame(&mut self) {
+        self.mouse_delta = glam::Vec2::ZERO;
+        self.pressed.clear();
+        self.released.clear();
+    }
+}
@@ -1,93 +1,1230 @@
 use pollster::FutureExt as _;
 use winit::{
-    dpi::PhysicalSize,
+    dpi::{
+        PhysicalPosition, PhysicalSize,
+    },
     event::{
-        Event, WindowEvent,
+        Event, WindowEvent, DeviceEvent,
+        KeyboardInput, ModifiersState,
         StartCause,
     },
     event_loop::{
-        EventLoop, EventLoopWindowTarget,
+        EventLoop, EventLoopBuilder, EventLoopProxy, EventLoopWindowTarget,
         ControlFlow,
     },
+    monitor::MonitorHandle,
     window::{
-        Window, WindowBuilder,
+        CursorGrabMode, Fullscreen, Window, WindowBuilder,
+    },
+};
+use std::{
+    any::Any,
+    collections::HashMap,
+    future::Future,
+    iter,
+    ops::RangeBounds,
+    path::Path,
+    sync::Arc,
+    time::{
+        Duration, Instant,
     },
 };
-use std::iter;
+
+mod accumulate;
+#[cfg(feature = "audio")]
+mod audio;
+mod adaptive;
+mod bloom;
+mod cache;
+mod camera;
+mod capture;
+#[cfg(feature = "ktx2")]
+mod compressed;
+mod debug;
+#[cfg(feature = "demo")]
+mod demo;
+mod error;
+#[cfg(test)]
+mod gpu_tests;
+#[cfg(feature = "overlay")]
+mod gpu_timer;
+mod grid;
+mod input;
+mod light;
+mod loader;
+mod mesh;
+#[cfg(feature = "obj")]
+mod obj;
+#[cfg(feature = "overlay")]
+mod overlay;
+mod particles;
+mod passes;
+mod pipelines;
+#[cfg(feature = "settings")]
+mod settings;
+mod resources;
+mod sampler;
+mod shapes;
+mod sprite;
+mod stats;
+mod target;
+mod text;
+mod uniforms;
+mod upload;
+
+pub use adaptive::AdaptiveResSettings;
+pub use bloom::BloomSettings;
+pub use cache::BindGroupCache;
+pub use camera::{
+    Aabb, Camera, Frustum, Viewport,
+};
+use camera::ViewUniforms;
+#[cfg(feature = "demo")]
+pub use demo::demo_scene;
+pub use error::AndError;
+pub use glam;
+pub use grid::GridSettings;
+use grid::Grid;
+pub use input::Input;
+pub use light::DirectionalLight;
+use light::Light;
+pub use loader::{
+    DecodedImage, Texture, TextureHandle,
+    premultiply_alpha,
+};
+#[cfg(feature = "settings")]
+pub use settings::Settings;
+use accumulate::Accumulation;
+#[cfg(feature = "audio")]
+use audio::Audio;
+use adaptive::AdaptiveResolution;
+use bloom::Bloom;
+use capture::{
+    Readback, Recorder,
+};
+use debug::DebugLines;
+#[cfg(feature = "overlay")]
+use gpu_timer::GpuTimer;
+use loader::Loader;
+pub use mesh::{
+    MeshDraw, Submesh, Vertex,
+};
+#[cfg(feature = "obj")]
+pub use obj::{
+    load_obj,
+    ObjMaterial, ObjModel,
+};
+use mesh::{
+    Mesh, MeshDraws, MeshPipelines,
+};
+use upload::Uploader;
+pub use particles::{
+    Particle,
+    Particles,
+};
+use particles::ParticleBatch;
+pub use passes::Passes;
+pub use pipelines::{
+    PipelineBuilder, PipelineTarget, Pipelines, RenderContext,
+};
+pub use resources::{
+    ResourceUsage, Tracked,
+};
+pub use sampler::AddressModes;
+use sampler::Samplers;
+pub use shapes::{
+    CrosshairSettings, Rect,
+};
+use shapes::Shapes;
+pub use sprite::{
+    BlendMode, Sprite, SpriteSortMode,
+};
+use sprite::SpriteBatch;
+pub use stats::FrameStats;
+use stats::FrameTimes;
+pub use target::{
+    StencilSettings, Tonemap,
+};
+use target::{
+    Blit, DepthTarget, FrameTarget, MsaaTarget,
+};
+use text::DebugText;
+pub use uniforms::Uniforms;
 
 #[cfg(target_os = "android")]
 #[ndk_glue::main]
 fn main() {
-    run();
+    run(Config::default());
 }
 
-struct State {
-    window: Window,
-    surface: wgpu::Surface,
-    config: wgpu::SurfaceConfiguration,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    pipeline: wgpu::RenderPipeline,
+/// Events other threads can send through [`State::event_loop_proxy`] to wake the event loop.
+pub enum UserEvent {
+    /// Request a redraw, e.g. because new data arrived.
+    Redraw,
+    /// Passed on to [`Config::on_user_event`].
+    Custom(Box<dyn Any + Send>),
 }
 
-impl State {
-    fn new(event_loop: &EventLoopWindowTarget<()>) -> Self {
-        let window = WindowBuilder::new()
-            .with_title("And".to_string())
-            .with_resizable(false)
-            .with_window_icon((|| {
-                #[cfg(target_os = "android")]
-                return None;
-                #[cfg(not(target_os = "android"))]
-                {
-                    use image::{
-                        load_from_memory_with_format,
-                        ImageFormat,
-                    };
-                    use winit::window::Icon;
-
-                    let bytes = include_bytes!("../res/mipmap-xxxhdpi/icon.png");
-                    let img = load_from_memory_with_format(bytes, ImageFormat::Png)
-                        .expect("Couldn't load icon")
-                        .into_rgba8();
-                    let (width, height) = img.dimensions();
-                    Some(Icon::from_rgba(img.into_vec(), width, height).expect("Couldn't set icon"))
-                }
-            })())
-            .build(&event_loop)
-            .expect("Unable to create window");
-        let PhysicalSize { width, height, } = window.inner_size();
+pub type FrameCallback = Box<dyn FnMut(&mut State)>;
+pub type KeyCallback = Box<dyn FnMut(&mut State, KeyboardInput)>;
+pub type UserEventCallback = Box<dyn FnMut(&mut State, Box<dyn Any + Send>)>;
+pub type EncoderCallback = Box<dyn FnMut(&mut wgpu::CommandEncoder)>;
+pub type SubmitCallback = Box<dyn FnMut(&wgpu::Device, &wgpu::Queue)>;
+pub type RenderCallback = Box<dyn for<'a> FnMut(&mut wgpu::RenderPass<'a>, &RenderContext<'a>)>;
+pub type FrameRenderCallback = Box<dyn FnMut(&mut wgpu::CommandEncoder, &wgpu::TextureView)>;
+
+/// User-facing configuration, consumed by [`run`].
+pub struct Config {
+    /// Invoked with the new `(width, height)` after the surface has been reconfigured to it.
+    pub on_resize: Option<Box<dyn FnMut(u32, u32)>>,
+    /// Invoked at the start of every frame, before anything is rendered. This is where immediate-mode drawing such
+    /// as [`State::draw_line`] goes.
+    pub on_frame: Option<FrameCallback>,
+    /// Invoked on every keyboard event. [`State::modifiers`] and [`State::input`] are up to date by then.
+    pub on_key: Option<KeyCallback>,
+    /// Pass the OS's repeats of a held key on to [`Config::on_key`], `true` by default, as text entry wants. Turn this
+    /// off for game input, so only the initial press of a key comes through. [`Input::just_pressed`] ignores repeats
+    /// either way.
+    pub key_repeats: bool,
+    /// Invoked with the payload of every [`UserEvent::Custom`].
+    pub on_user_event: Option<UserEventCallback>,
+    /// Invoked with the frame's command encoder before the scene pass begins, after the crate's own uploads were
+    /// recorded. Copies and clears recorded here go out in the same submission as the frame, ahead of any drawing.
+    /// Only frames rendered to the surface invoke this.
+    pub on_pre_render: Option<EncoderCallback>,
+    /// Invoked right after the frame is submitted, before it's presented. This is where to map buffers that work
+    /// recorded in [`Config::on_pre_render`] copied into, e.g. for screenshots or picking, without another submission.
+    pub on_post_submit: Option<SubmitCallback>,
+    /// Invoked within the scene pass, after the crate's own views are drawn and before sprites and shapes go on
+    /// top, with the viewport reset to the whole target. Pipelines registered through [`State::register_pipeline`]
+    /// are looked up by name from the context.
+    pub on_render: Option<RenderCallback>,
+    /// Takes over rendering entirely: when set, every frame's encoder and surface view go straight to this, and
+    /// nothing of the crate's own is drawn. The scene pass, post-processing, the blit and recording are all skipped,
+    /// along with [`Config::on_pre_render`] and [`Config::on_render`], while the window, device, surface and event
+    /// loop are still managed as usual. The view is in [`State::surface_format`] and has to be cleared or fully
+    /// covered, since its previous contents are undefined.
+    pub on_render_frame: Option<FrameRenderCallback>,
+    /// Invoked once [`run`] recovered from losing the device, on the fresh device. The mesh and textures loaded
+    /// through [`State::load_texture_async`] don't survive that, and their [`TextureHandle`]s are left dangling, so
+    /// this is where to set and load them again.
+    pub on_device_recovered: Option<FrameCallback>,
+    /// Device limits to request; see [`LimitsPreset`].
+    pub limits_preset: LimitsPreset,
+    /// Map the near plane to depth 1 and the far plane to depth 0 for better precision across large scenes. Flips
+    /// the depth test and clear value, and the [`Camera`]'s projection.
+    pub reverse_z: bool,
+    /// Which faces to cull, `Back` by default. Changing this at runtime rebuilds the pipeline; see
+    /// [`State::set_cull_mode`].
+    pub cull_mode: Option<wgpu::Face>,
+    /// Winding order of front faces, `Ccw` by default. Changing this at runtime rebuilds the pipeline; see
+    /// [`State::set_front_face`].
+    pub front_face: wgpu::FrontFace,
+    /// How the scene's vertices assemble into primitives, `TriangleList` by default, e.g. `PointList` for point
+    /// clouds. Changing this at runtime rebuilds the pipeline; see [`State::set_topology`].
+    pub topology: wgpu::PrimitiveTopology,
+    /// Forces a specific surface format instead of the adapter's preferred one, e.g. for pixel-exact captures.
+    /// Initialization fails with [`AndError::UnsupportedFormat`] if the surface doesn't support it. Changing this at
+    /// runtime reconfigures the surface; see [`State::set_surface_format`].
+    pub surface_format: Option<wgpu::TextureFormat>,
+    /// Set up a platform logger on startup, `true` by default. An already installed logger is kept either way, so
+    /// embedding apps that own logging can leave this on or turn it off.
+    pub init_logger: bool,
+    /// Most verbose level the platform logger lets through, `Info` by default. `RUST_LOG` takes precedence when set,
+    /// on Android too.
+    pub log_level: log::LevelFilter,
+    /// Allow [`run`] and `run_from_settings` to be called off the main thread, e.g. from a thread an embedding app
+    /// spawned for rendering. Only Windows and the Unix platforms (X11 and Wayland) support this and it's ignored
+    /// elsewhere, where the loop still has to run on the main thread. Off by default, so running on the wrong
+    /// thread panics rather than misbehaving on platforms that care.
+    pub any_thread: bool,
+    /// Whether the window has a title bar and borders, `true` by default.
+    pub decorations: bool,
+    /// Keep the window above all others, for overlay or tool windows.
+    pub always_on_top: bool,
+    /// Initial window position, relative to the top-left of [`Config::monitor`] if set. The OS decides by default.
+    pub position: Option<PhysicalPosition<i32>>,
+    /// Index into the available monitors to open the window on. Falls back to the primary monitor if there's no such
+    /// monitor.
+    pub monitor: Option<usize>,
+    /// Tonemapping applied when presenting. Anything but [`Tonemap::None`] renders the scene into a linear
+    /// `Rgba16Float` target, so values above 1 survive until presenting.
+    pub tonemap: Tonemap,
+    /// Display calibration applied when presenting, after tonemapping: the frame is raised to the power of
+    /// `1 / gamma`, so values above 1 brighten midtones and below darken them. 1 by default for none, and clamped
+    /// to `0.5..=2.5`; see [`State::set_gamma`].
+    pub gamma: f32,
+    /// Multiplies the frame when presenting, before [`Config::gamma`]. 1 by default for none, and clamped to
+    /// `0.5..=2.0`; see [`State::set_brightness`].
+    pub brightness: f32,
+    /// Render the scene into a linear `Rgba16Float` target so shaders can write values above 1, which are clamped
+    /// (or tonemapped, see [`Config::tonemap`]) when presenting. Falls back to the surface format if the adapter can't
+    /// render to that format.
+    pub hdr: bool,
+    /// Bleed bright parts of the scene into their surroundings, off by default. Best paired with [`Config::hdr`], so
+    /// there's something above the threshold to begin with.
+    pub bloom: Option<BloomSettings>,
+    /// Average up to this many frames into a float history and present the average, for progressive rendering
+    /// that converges over time, e.g. path tracing or jittered sampling. Starts over whenever a camera moves, and on
+    /// [`State::reset_accumulation`]. 0, the default, turns this off; anything else renders the scene into an
+    /// `Rgba16Float` target like [`Config::hdr`].
+    pub accumulation_frames: u32,
+    /// Draw an infinite, anti-aliased ground grid on the XZ plane from every view, fading with distance. Off by
+    /// default; see [`State::set_grid`].
+    pub grid: Option<GridSettings>,
+    /// Draw a crosshair at the center of the frame, on top of everything else, e.g. for first-person prototypes. Off
+    /// by default; see [`State::set_crosshair`].
+    pub crosshair: Option<CrosshairSettings>,
+    /// How frames are presented with [`PresentModePolicy::Auto`], `AutoVsync` by default. `Immediate` presents
+    /// without waiting for vertical blank, tearing in exchange for latency, and keeps redrawing continuously.
+    /// Unsupported modes fall back to `Fifo`.
+    pub present_mode: wgpu::PresentMode,
+    /// Picks the present mode from what the surface supports; [`PresentModePolicy::Smooth`] by default. Set this to
+    /// [`PresentModePolicy::Auto`] to use [`Config::present_mode`] instead.
+    pub present_mode_policy: PresentModePolicy,
+    /// When the event loop renders frames on its own, [`ControlFlowPolicy::Wait`] by default, which only renders
+    /// when something asks for a redraw. Continuously animating apps want `Poll` or `WaitUntil`.
+    pub control_flow: ControlFlowPolicy,
+    /// Initial inner size of the window. The OS decides by default.
+    pub size: Option<PhysicalSize<u32>>,
+    /// Cell size in physical pixels the window snaps to when resized, e.g. for terminal-like or tile-based apps.
+    /// Setting this also makes the window resizable, and [`Config::size`] is rounded down to whole cells. Only read on
+    /// startup, and only honored on X11 and macOS; the window resizes freely elsewhere.
+    pub resize_increments: Option<(u32, u32)>,
+    /// Whether and how to open fullscreen, on [`Config::monitor`] if set. Windowed by default.
+    pub fullscreen: FullscreenMode,
+    /// Let the desktop show through wherever the frame isn't opaque, picking a premultiplied surface alpha mode where
+    /// available. Only some platforms and compositors support this.
+    pub transparent: bool,
+    /// Color the scene is cleared to, with straight (not premultiplied) alpha. It's premultiplied as needed, as is
+    /// everything the crate blends onto it.
+    pub clear_color: wgpu::Color,
+    /// Lower the render resolution when frames take longer than the target, and raise it again when there's
+    /// headroom. Off by default; see [`State::set_render_scale`] for a fixed scale instead.
+    pub adaptive_resolution: Option<AdaptiveResSettings>,
+    /// Clear the color attachment to [`Config::clear_color`] before drawing the scene, `true` by default. Turn this
+    /// off to draw on top of what's already there, e.g. content in a texture passed to [`State::render_to_texture`].
+    /// On screen, that's whatever the previous frame left. Depth is cleared as per [`Config::clear_depth`]. With
+    /// MSAA, what's drawn on top of is the multisampled attachment's own last contents, not the texture's. Only the
+    /// scene pass clears; passes from [`State::register_pass`] draw on top of it unless they ask otherwise.
+    pub clear: bool,
+    /// Clear the depth attachment before drawing the scene, `true` by default. Turn this off to keep the previous
+    /// pass's depth, e.g. to depth-test against geometry from an earlier frame or [`State::render_to_texture`]
+    /// call of the same size. Depth targets start out zeroed whenever they're (re)created, such as on resize, and
+    /// loading one that nothing was rendered into yet warns.
+    pub clear_depth: bool,
+    /// Aspect ratio, width over height, to keep the scene at whatever shape the window is. The scene is rendered at
+    /// the largest size of this ratio that fits the window and shown centered, with black bars filling the rest.
+    /// Off by default, stretching the scene over the window; see [`State::set_aspect`].
+    pub aspect: Option<f32>,
+    /// Turn the scene's fragment alpha into a coverage mask, for anti-aliased edges on cutout geometry such as
+    /// foliage without sorting. Needs MSAA, and a fragment shader that writes meaningful alpha; ignored without
+    /// MSAA. Off by default; see [`State::set_alpha_to_coverage`].
+    pub alpha_to_coverage: bool,
+    /// Samples per pixel of the scene pass, 1 by default for no MSAA. The scene is resolved down to one sample at the
+    /// end of the pass, before bloom and accumulation. Falls back to 1 with a warning if the adapter can't
+    /// multisample the scene's formats that many times; see [`State::set_msaa`].
+    pub msaa_samples: u32,
+    /// Draw transparent [`MeshDraw`]s back to front as seen from each view's camera, `true` by default, so they
+    /// blend in the right order without a depth pre-pass. Off, they're drawn in the order they were queued.
+    pub sort_transparency: bool,
+    /// Shade meshes that have a `Float32x3` normal at shader location 1, such as ones in the [`Vertex`] layout, with
+    /// a directional light. Off by default, drawing every mesh in its flat [`MeshDraw`] color; see
+    /// [`State::set_light`].
+    pub light: Option<DirectionalLight>,
+    /// Render a throwaway frame on startup, and after recovering from device loss, so drivers that compile shaders
+    /// on first use do it before the first real frame; see [`State::prewarm`]. Off by default.
+    pub prewarm: bool,
+    /// Give the scene a stencil attachment, cleared and tested as set here, e.g. for masking UI regions. Off by
+    /// default, which keeps a pure `Depth32Float` depth attachment; see [`State::set_stencil`].
+    pub stencil: Option<StencilSettings>,
+    /// Address modes of the samplers in [`State::texture_bind_group`]s, clamping to the edge by default. Textures
+    /// can override this; see [`State::set_texture_address_modes`]. Initialization fails with
+    /// [`AndError::UnsupportedAddressMode`] if the device can't clamp to the border as asked.
+    pub address_modes: AddressModes,
+    /// How many frame times [`State::frame_time_history`] retains, 120 by default.
+    pub frame_time_history: usize,
+    /// Decode and set the embedded window icon, `true` by default. Failing to do so only logs a warning.
+    pub load_icon: bool,
+    /// Render a frame in the middle of processing events once this many have been handled or this long has passed
+    /// since the last frame, so a flood of input such as rapid mouse motion can't hold frames back until it's all
+    /// processed. The remaining events are handled after that frame. Off by default.
+    pub event_budget: Option<EventBudget>,
+    /// Submit frames without presenting them, leaving that to [`State::take_pending_frame`] and [`State::present`],
+    /// e.g. to do other work or present from another thread in latency experiments. Off by default.
+    pub defer_present: bool,
+    /// Frames the CPU can be ahead of the GPU by without waiting on it to finish reading uniforms this frame writes,
+    /// 2 by default. Every buffer set through [`State::set_uniform`] is kept this many times, and frames cycle
+    /// through them. 1 keeps a single copy.
+    pub frames_in_flight: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            on_resize: None,
+            on_frame: None,
+            on_key: None,
+            key_repeats: true,
+            on_user_event: None,
+            on_pre_render: None,
+            on_post_submit: None,
+            on_render: None,
+            on_render_frame: None,
+            on_device_recovered: None,
+            limits_preset: LimitsPreset::default(),
+            reverse_z: false,
+            cull_mode: Some(wgpu::Face::Back),
+            front_face: wgpu::FrontFace::Ccw,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            surface_format: None,
+            init_logger: true,
+            log_level: log::LevelFilter::Info,
+            any_thread: false,
+            decorations: true,
+            always_on_top: false,
+            position: None,
+            monitor: None,
+            tonemap: Tonemap::None,
+            gamma: 1.0,
+            brightness: 1.0,
+            hdr: false,
+            bloom: None,
+            accumulation_frames: 0,
+            grid: None,
+            crosshair: None,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            present_mode_policy: PresentModePolicy::default(),
+            control_flow: ControlFlowPolicy::default(),
+            size: None,
+            resize_increments: None,
+            fullscreen: FullscreenMode::default(),
+            transparent: false,
+            clear_color: wgpu::Color { r: 0.1, g: 0.2, b: 0.3, a: 1.0, },
+            adaptive_resolution: None,
+            clear: true,
+            clear_depth: true,
+            aspect: None,
+            alpha_to_coverage: false,
+            msaa_samples: 1,
+            sort_transparency: true,
+            light: None,
+            prewarm: false,
+            stencil: None,
+            address_modes: AddressModes::default(),
+            frame_time_history: 120,
+            load_icon: true,
+            event_budget: None,
+            defer_present: false,
+            frames_in_flight: 2,
+        }
+    }
+}
+
+impl Config {
+    /// Every setting, with the callbacks left out since they can't be copied.
+    fn without_callbacks(&self) -> Self {
+        Self {
+            on_resize: None,
+            on_frame: None,
+            on_key: None,
+            key_repeats: self.key_repeats,
+            on_user_event: None,
+            on_pre_render: None,
+            on_post_submit: None,
+            on_render: None,
+            on_render_frame: None,
+            on_device_recovered: None,
+            limits_preset: self.limits_preset,
+            reverse_z: self.reverse_z,
+            cull_mode: self.cull_mode,
+            front_face: self.front_face,
+            topology: self.topology,
+            surface_format: self.surface_format,
+            init_logger: self.init_logger,
+            log_level: self.log_level,
+            any_thread: self.any_thread,
+            decorations: self.decorations,
+            always_on_top: self.always_on_top,
+            position: self.position,
+            monitor: self.monitor,
+            tonemap: self.tonemap,
+            gamma: self.gamma,
+            brightness: self.brightness,
+            hdr: self.hdr,
+            bloom: self.bloom,
+            accumulation_frames: self.accumulation_frames,
+            grid: self.grid,
+            crosshair: self.crosshair,
+            present_mode: self.present_mode,
+            present_mode_policy: self.present_mode_policy,
+            control_flow: self.control_flow,
+            size: self.size,
+            resize_increments: self.resize_increments,
+            fullscreen: self.fullscreen,
+            transparent: self.transparent,
+            clear_color: self.clear_color,
+            adaptive_resolution: self.adaptive_resolution,
+            clear: self.clear,
+            clear_depth: self.clear_depth,
+            aspect: self.aspect,
+            alpha_to_coverage: self.alpha_to_coverage,
+            msaa_samples: self.msaa_samples,
+            sort_transparency: self.sort_transparency,
+            light: self.light,
+            prewarm: self.prewarm,
+            stencil: self.stencil,
+            address_modes: self.address_modes,
+            frame_time_history: self.frame_time_history,
+            load_icon: self.load_icon,
+            event_budget: self.event_budget,
+            defer_present: self.defer_present,
+            frames_in_flight: self.frames_in_flight,
+        }
+    }
+}
+
+/// Maps to the [`wgpu::Limits`] constructors, from the most to the least demanding. Defaults to
+/// [`LimitsPreset::WebGL2`] on web targets.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LimitsPreset {
+    #[cfg_attr(not(target_arch = "wasm32"), default)]
+    Default,
+    Downlevel,
+    #[cfg_attr(target_arch = "wasm32", default)]
+    WebGL2,
+}
+
+impl LimitsPreset {
+    pub fn limits(self) -> wgpu::Limits {
+        match self {
+            Self::Default => wgpu::Limits::default(),
+            Self::Downlevel => wgpu::Limits::downlevel_defaults(),
+            Self::WebGL2 => wgpu::Limits::downlevel_webgl2_defaults(),
+        }
+    }
+
+    /// The next less demanding preset, if any.
+    pub fn lower(self) -> Option<Self> {
+        match self {
+            Self::Default => Some(Self::Downlevel),
+            Self::Downlevel => Some(Self::WebGL2),
+            Self::WebGL2 => None,
+        }
+    }
+}
+
+/// Fullscreen modes the window can open in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FullscreenMode {
+    #[default]
+    Windowed,
+    /// A borderless window covering the monitor, at its current video mode.
+    Borderless,
+    /// Exclusive fullscreen at a `width` by `height` video mode refreshing at `refresh` Hz. If the monitor has no such
+    /// mode, the closest one is used instead, preferring the right size over the right refresh rate.
+    Exclusive(u32, u32, u32),
+}
+
+/// Bounds on how much event processing may happen between two frames; see [`Config::event_budget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventBudget {
+    pub max_events: usize,
+    pub max_time: Duration,
+}
+
+impl Default for EventBudget {
+    fn default() -> Self {
+        Self { max_events: 256, max_time: Duration::from_millis(8), }
+    }
+}
+
+/// A rendered and submitted frame waiting to be presented, with [`Config::defer_present`] set.
+pub struct PendingFrame {
+    output: wgpu::SurfaceTexture,
+}
+
+impl PendingFrame {
+    /// Queues the frame for display, e.g. from another thread. [`State::present`] does the same, but also keeps
+    /// [`State::predicted_present_time`] accurate.
+    pub fn present(self) {
+        self.output.present();
+    }
+}
+
+/// How to pick a present mode out of the ones the surface supports.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PresentModePolicy {
+    /// Use [`Config::present_mode`] as is.
+    Auto,
+    /// Prefer `Mailbox`, which always shows the newest frame at vertical blank, for the lowest latency that doesn't
+    /// tear. Falls back to `Fifo` where `Mailbox` isn't supported.
+    #[default]
+    Smooth,
+    /// Prefer `Mailbox`, which doesn't tear, then `Immediate`, which does.
+    LowLatency,
+    /// Wait for vertical blank with `Fifo`, never rendering frames that won't be shown.
+    PowerSaving,
+}
+
+impl PresentModePolicy {
+    /// Modes in order of preference, or `None` to defer to [`Config::present_mode`].
+    fn preference(self) -> Option<&'static [wgpu::PresentMode]> {
+        match self {
+            Self::Auto => None,
+            Self::Smooth => Some(&[wgpu::PresentMode::Mailbox, wgpu::PresentMode::Fifo]),
+            Self::LowLatency => Some(&[wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate]),
+            Self::PowerSaving => Some(&[wgpu::PresentMode::Fifo]),
+        }
+    }
+}
+
+/// How the event loop waits between frames; see [`Config::control_flow`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ControlFlowPolicy {
+    /// Sleep until an event arrives, rendering only when something requests a redraw, such as input handlers or
+    /// finished texture loads.
+    #[default]
+    Wait,
+    /// Render again as soon as the last frame is done, paced only by the present mode.
+    Poll,
+    /// Render continuously at up to this many frames per second, at least 1, sleeping in between.
+    WaitUntil(f32),
+}
+
+#[cfg(target_os = "android")]
+fn window_icon() -> Option<winit::window::Icon> {
+    None
+}
+
+#[cfg(not(target_os = "android"))]
+fn window_icon() -> Option<winit::window::Icon> {
+    use image::{
+        load_from_memory_with_format,
+        ImageFormat,
+    };
+    use winit::window::Icon;
+
+    let bytes = include_bytes!("../res/mipmap-xxxhdpi/icon.png");
+    let img = load_from_memory_with_format(bytes, ImageFormat::Png)
+        .map_err(|e| log::warn!("Couldn't load icon: {e}"))
+        .ok()?
+        .into_rgba8();
+    let (width, height) = img.dimensions();
+    Icon::from_rgba(img.into_vec(), width, height)
+        .map_err(|e| log::warn!("Couldn't set icon: {e}"))
+        .ok()
+}
+
+/// Vertex state of the placeholder triangle, which is generated from the vertex index alone.
+fn triangle_vertex(shader: &wgpu::ShaderModule) -> wgpu::VertexState<'_> {
+    wgpu::VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[],
+    }
+}
+
+/// Strip topologies need to know the index format to tell the primitive restart value apart, and others mustn't be
+/// given one. Meshes are always indexed with `Uint32`; the placeholder triangle isn't indexed, so it doesn't care.
+fn strip_index_format(topology: wgpu::PrimitiveTopology) -> Option<wgpu::IndexFormat> {
+    topology.is_strip().then_some(wgpu::IndexFormat::Uint32)
+}
+
+/// A pipeline drawing into the scene pass with the scene's pipeline state. `transparent` ones are alpha blended and
+/// test depth without writing it.
+fn scene_pipeline(
+    device: &wgpu::Device, layout: &wgpu::PipelineLayout, vertex: wgpu::VertexState, fragment_entry: &str,
+    transparent: bool, format: wgpu::TextureFormat, user_config: &Config,
+) -> wgpu::RenderPipeline {
+    let sample_count = user_config.msaa_samples;
+    let shader = vertex.module;
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(if transparent { "Transparent pipeline" } else { "Pipeline" }),
+        layout: Some(layout),
+        vertex,
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fragment_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(if transparent { wgpu::BlendState::ALPHA_BLENDING } else { wgpu::BlendState::REPLACE }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: user_config.topology,
+            strip_index_format: strip_index_format(user_config.topology),
+            front_face: user_config.front_face,
+            cull_mode: user_config.cull_mode,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: target::depth_format(user_config.stencil.is_some()),
+            depth_write_enabled: !transparent,
+            depth_compare: if user_config.reverse_z {
+                wgpu::CompareFunction::GreaterEqual
+            } else {
+                wgpu::CompareFunction::Less
+            },
+            stencil: user_config.stencil.map(|stencil| stencil.state()).unwrap_or_default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: user_config.alpha_to_coverage && sample_count > 1,
+        },
+        multiview: None,
+    })
+}
+
+/// [`Config::monitor`], falling back to the primary monitor, or `None` if it's unset.
+fn startup_monitor(event_loop: &EventLoopWindowTarget<UserEvent>, user_config: &Config) -> Option<MonitorHandle> {
+    let index = user_config.monitor?;
+    event_loop.available_monitors().nth(index).or_else(|| {
+        log::warn!("There's no monitor #{index}, falling back to the primary one");
+        event_loop.primary_monitor()
+    })
+}
+
+fn startup_position(
+    event_loop: &EventLoopWindowTarget<UserEvent>, user_config: &Config,
+) -> Option<PhysicalPosition<i32>> {
+    if user_config.monitor.is_none() { return user_config.position };
+
+    let monitor = startup_monitor(event_loop, user_config)?;
+    let origin = monitor.position();
+    let offset = user_config.position.unwrap_or_default();
+    Some(PhysicalPosition::new(origin.x + offset.x, origin.y + offset.y))
+}
+
+/// Rounds `size` down to whole [`Config::resize_increments`] cells, keeping at least one.
+fn snap_to_increments(size: PhysicalSize<u32>, increments: Option<(u32, u32)>) -> PhysicalSize<u32> {
+    let Some((cell_width, cell_height)) = increments else { return size };
+
+    let snap = |length: u32, cell: u32| length.checked_div(cell).map_or(length, |cells| cells.max(1) * cell);
+    PhysicalSize::new(snap(size.width, cell_width), snap(size.height, cell_height))
+}
+
+/// Applies [`Config::resize_increments`] where winit supports it. Wayland ignores them, despite sharing the Unix
+/// builder extension with X11.
+fn with_resize_increments(
+    builder: WindowBuilder, event_loop: &EventLoopWindowTarget<UserEvent>, user_config: &Config,
+    (width, height): (u32, u32),
+) -> WindowBuilder {
+    #[cfg(any(
+        target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    {
+        let _ = (event_loop, user_config);
+        winit::platform::unix::WindowBuilderExtUnix::with_resize_increments(builder, PhysicalSize::new(width, height))
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // macOS takes points, which the monitor the window opens on scales.
+        let scale = startup_monitor(event_loop, user_config)
+            .or_else(|| event_loop.primary_monitor())
+            .map_or(1.0, |monitor| monitor.scale_factor());
+        let increments = PhysicalSize::new(width, height).to_logical(scale);
+        winit::platform::macos::WindowBuilderExtMacOS::with_resize_increments(builder, increments)
+    }
+    #[cfg(not(any(
+        target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd",
+        target_os = "openbsd", target_os = "macos",
+    )))]
+    {
+        let _ = (event_loop, user_config, width, height);
+        log::warn!("Resize increments aren't supported here, ignoring Config::resize_increments");
+        builder
+    }
+}
+
+/// Resolves [`Config::fullscreen`] against the video modes of the monitor the window opens on.
+fn startup_fullscreen(event_loop: &EventLoopWindowTarget<UserEvent>, user_config: &Config) -> Option<Fullscreen> {
+    let monitor = startup_monitor(event_loop, user_config);
+    let (width, height, refresh) = match user_config.fullscreen {
+        FullscreenMode::Windowed => return None,
+        FullscreenMode::Borderless => return Some(Fullscreen::Borderless(monitor)),
+        FullscreenMode::Exclusive(width, height, refresh) => (width, height, refresh),
+    };
+
+    let Some(monitor) = monitor.or_else(|| event_loop.primary_monitor()) else {
+        log::warn!("There's no monitor to go exclusive fullscreen on, opening borderless");
+        return Some(Fullscreen::Borderless(None));
+    };
+
+    // Closest size first, then closest refresh rate, which is reported in millihertz.
+    let distance = |mode: &winit::monitor::VideoMode| {
+        let size = mode.size();
+        (
+            size.width.abs_diff(width) as u64 + size.height.abs_diff(height) as u64,
+            mode.refresh_rate_millihertz().abs_diff(refresh * 1000),
+        )
+    };
+    let Some(mode) = monitor.video_modes().min_by_key(distance) else {
+        log::warn!("Monitor {:?} reports no video modes, opening borderless", monitor.name());
+        return Some(Fullscreen::Borderless(Some(monitor)));
+    };
+
+    if distance(&mode) != (0, 0) {
+        log::warn!(
+            "There's no {width}x{height} @ {refresh} Hz video mode, using the closest one, {}x{} @ {:.2} Hz",
+            mode.size().width, mode.size().height, mode.refresh_rate_millihertz() as f32 / 1000.0,
+        );
+    }
+    Some(Fullscreen::Exclusive(mode))
+}
+
+/// Of the monitor the window is on, in hertz; winit reports it in millihertz, and zero on some platforms.
+fn refresh_rate(window: &Window) -> Option<f32> {
+    let millihertz = window.current_monitor()?.refresh_rate_millihertz()?;
+    (millihertz > 0).then(|| millihertz as f32 / 1000.0)
+}
+
+fn scene_format(
+    user_config: &Config, surface_format: wgpu::TextureFormat, adapter: &wgpu::Adapter,
+) -> wgpu::TextureFormat {
+    const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    if !user_config.hdr && user_config.tonemap == Tonemap::None && user_config.accumulation_frames == 0 {
+        return surface_format;
+    }
+
+    let usages = adapter.get_texture_format_features(HDR_FORMAT).allowed_usages;
+    if usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING) {
+        HDR_FORMAT
+    } else {
+        log::warn!("{HDR_FORMAT:?} can't be rendered to and sampled, falling back to {surface_format:?}");
+        surface_format
+    }
+}
+
+/// The largest rectangle of `aspect` centered in `surface`, or all of it without a usable aspect ratio.
+fn letterbox(surface: glam::Vec2, aspect: Option<f32>) -> Rect {
+    let Some(aspect) = aspect.filter(|aspect| aspect.is_finite() && *aspect > 0.0) else {
+        return Rect { position: glam::Vec2::ZERO, size: surface, };
+    };
+
+    let size = if surface.x / surface.y > aspect {
+        glam::Vec2::new(surface.y * aspect, surface.y)
+    } else {
+        glam::Vec2::new(surface.x, surface.x / aspect)
+    };
+    // Whole pixels, so the scene isn't resampled across a fractional edge.
+    let size = size.round().max(glam::Vec2::ONE);
+    Rect { position: ((surface - size) * 0.5).floor(), size, }
+}
+
+/// Whether presenting a `scene_format` frame to a `surface_format` surface needs manual sRGB encoding.
+fn encode_srgb(scene_format: wgpu::TextureFormat, surface_format: wgpu::TextureFormat) -> bool {
+    scene_format != surface_format && !scene_format.describe().srgb && !surface_format.describe().srgb
+}
+
+/// Sample counts `format` can be rendered with, always including 1. Color formats also have to be resolvable, since
+/// that's the only way to use what's rendered into them. wgpu only reports whether a format can be multisampled at
+/// all, with no per-count flags, and WebGPU only guarantees that for 4 samples, so that's the only other count.
+fn format_sample_counts(adapter: &wgpu::Adapter, format: wgpu::TextureFormat) -> Vec<u32> {
+    let depth = format.describe().sample_type == wgpu::TextureSampleType::Depth;
+    multisample_counts(adapter.get_texture_format_features(format).flags, depth)
+}
+
+/// [`format_sample_counts`] for a format with these features.
+fn multisample_counts(flags: wgpu::TextureFormatFeatureFlags, depth: bool) -> Vec<u32> {
+    let mut required = wgpu::TextureFormatFeatureFlags::MULTISAMPLE;
+    if !depth {
+        required |= wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE;
+    }
+
+    if flags.contains(required) {
+        vec![1, 4]
+    } else {
+        vec![1]
+    }
+}
+
+/// Sample counts the scene can be rendered with into `format` and `depth_format`.
+fn sample_counts(adapter: &wgpu::Adapter, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat) -> Vec<u32> {
+    let depth_counts = format_sample_counts(adapter, depth_format);
+    format_sample_counts(adapter, format).into_iter().filter(|count| depth_counts.contains(count)).collect()
+}
+
+/// Turns [`Config::msaa_samples`] down to 1 if the scene can't be multisampled that many times in `format`.
+fn fallback_msaa(user_config: &mut Config, adapter: &wgpu::Adapter, format: wgpu::TextureFormat) {
+    let depth_format = target::depth_format(user_config.stencil.is_some());
+    let samples = user_config.msaa_samples;
+    if !sample_counts(adapter, format, depth_format).contains(&samples) {
+        log::warn!("{samples}x MSAA isn't supported with {format:?} and {depth_format:?}, turning it off");
+        user_config.msaa_samples = 1;
+    }
+}
+
+/// The first mode the policy prefers that the surface supports, or [`Config::present_mode`] if supported. Falls
+/// back to `Fifo`, which is always supported, as are the `Auto*` modes.
+fn present_mode(user_config: &Config, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    if let Some(preference) = user_config.present_mode_policy.preference() {
+        let policy = user_config.present_mode_policy;
+        let Some(mode) = preference.iter().copied().find(|mode| supported.contains(mode)) else {
+            log::warn!("None of {preference:?} preferred by {policy:?} are supported, falling back to Fifo");
+            return wgpu::PresentMode::Fifo;
+        };
+
+        if mode != preference[0] {
+            log::info!("{:?} isn't supported, {policy:?} falls back to {mode:?}", preference[0]);
+        }
+        return mode;
+    }
+
+    match user_config.present_mode {
+        requested @ (wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync) => requested,
+        mode if supported.contains(&mode) => mode,
+        mode => {
+            log::warn!("Present mode {mode:?} isn't supported (only {supported:?}), falling back to Fifo");
+            wgpu::PresentMode::Fifo
+        },
+    }
+}
+
+/// The best alpha mode for a `transparent` window, preferring premultiplied alpha since that's what blending produces.
+fn alpha_mode(transparent: bool, supported: &[wgpu::CompositeAlphaMode]) -> wgpu::CompositeAlphaMode {
+    if !transparent {
+        return wgpu::CompositeAlphaMode::Auto;
+    }
+
+    let preferred = [
+        wgpu::CompositeAlphaMode::PreMultiplied,
+        wgpu::CompositeAlphaMode::PostMultiplied,
+        wgpu::CompositeAlphaMode::Inherit,
+    ];
+    match preferred.into_iter().find(|mode| supported.contains(mode)) {
+        Some(mode) => {
+            log::info!("Using alpha mode {mode:?}");
+            mode
+        },
+        None => {
+            log::warn!("Transparency isn't supported (only {supported:?}), the window will be opaque");
+            wgpu::CompositeAlphaMode::Auto
+        },
+    }
+}
+
+/// Straight-alpha `color` as a surface in `alpha_mode` expects it.
+fn surface_color(color: wgpu::Color, alpha_mode: wgpu::CompositeAlphaMode) -> wgpu::Color {
+    match alpha_mode {
+        wgpu::CompositeAlphaMode::PreMultiplied => wgpu::Color {
+            r: color.r * color.a,
+            g: color.g * color.a,
+            b: color.b * color.a,
+            a: color.a,
+        },
+        _ => color,
+    }
+}
+
+/// Why `adapter` can't present to `surface`, if it can't.
+fn adapter_rejection(adapter: &wgpu::Adapter, surface: &wgpu::Surface) -> Option<&'static str> {
+    if !adapter.is_surface_supported(surface) {
+        Some("surface isn't supported")
+    } else if surface.get_supported_formats(adapter).is_empty() {
+        Some("no supported surface formats")
+    } else if surface.get_supported_present_modes(adapter).is_empty() {
+        Some("no supported present modes")
+    } else {
+        None
+    }
+}
+
+/// Everything that has to be requested from the platform before the GPU can present to a window. Requesting these
+/// fails cleanly, so it can be retried without rebuilding the window.
+struct Gpu {
+    surface: wgpu::Surface,
+    adapter: wgpu::Adapter,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    format: wgpu::TextureFormat,
+    present_mode: wgpu::PresentMode,
+    alpha_mode: wgpu::CompositeAlphaMode,
+}
 
+impl Gpu {
+    fn new(window: &Window, user_config: &Config) -> Result<Self, AndError> {
         let instance = wgpu::Instance::new(wgpu::Backends::all());
-        let surface = unsafe { instance.create_surface(&window) };
+        let surface = unsafe { instance.create_surface(window) };
+        let usable = |adapter: &wgpu::Adapter| match adapter_rejection(adapter, &surface) {
+            None => true,
+            Some(reason) => {
+                log::warn!("Rejecting adapter {:?}: {reason}", adapter.get_info().name);
+                false
+            },
+        };
         let adapter = instance.request_adapter(
             &wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::default(),
                 compatible_surface: Some(&surface),
                 force_fallback_adapter: false,
             },
-        ).block_on().or_else(|| instance.enumerate_adapters(wgpu::Backends::all())
-            .filter(|adapter| !surface.get_supported_formats(&adapter).is_empty())
-            .next()
-        ).expect("Unable to request video adapter.");
-        let (device, queue) = adapter.request_device(
+        ).block_on().filter(usable).or_else(|| instance.enumerate_adapters(wgpu::Backends::all())
+            .find(usable)
+        ).ok_or(AndError::NoAdapter)?;
+        // Compressed formats are enabled wherever available, so KTX2 textures can skip decompression.
+        #[cfg(feature = "ktx2")]
+        let features = adapter.features() & compressed::FEATURES;
+        #[cfg(not(feature = "ktx2"))]
+        let features = wgpu::Features::empty();
+        // Only the overlay reads timestamps, and it shows no GPU time without them.
+        #[cfg(feature = "overlay")]
+        let features = features | (adapter.features() & wgpu::Features::TIMESTAMP_QUERY);
+        let features = features | (adapter.features() & wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER);
+        let request_device = |preset: LimitsPreset| adapter.request_device(
             &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
+                features,
+                limits: preset.limits(),
                 label: None,
             },
             None,
-        ).block_on().expect("Unable to request WGPU device and render queue");
+        ).block_on();
+
+        // Walks down the presets until one fits the hardware, failing with the last error if none does.
+        let mut preset = user_config.limits_preset;
+        let (device, queue) = loop {
+            log::debug!("Requesting device with {preset:?} limits");
+            match request_device(preset) {
+                Ok(pair) => break pair,
+                Err(err) => match preset.lower() {
+                    Some(lower) => {
+                        log::warn!("Couldn't request device with {preset:?} limits ({err}), retrying with {lower:?}");
+                        preset = lower;
+                    },
+                    None => return Err(err.into()),
+                },
+            }
+        };
+        log::info!("Using {preset:?} device limits");
+        Self::with_device(surface, adapter, Arc::new(device), Arc::new(queue), user_config)
+    }
+
+    /// Picks the surface's format, present mode and alpha mode for a device that's already there.
+    fn with_device(
+        surface: wgpu::Surface, adapter: wgpu::Adapter, device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>,
+        user_config: &Config,
+    ) -> Result<Self, AndError> {
+        user_config.address_modes.check(device.features())?;
+        let supported_formats = surface.get_supported_formats(&adapter);
+        let format = match user_config.surface_format {
+            Some(format) if supported_formats.contains(&format) => format,
+            Some(format) => return Err(AndError::UnsupportedFormat { format, supported: supported_formats, }),
+            None => supported_formats[0],
+        };
+        log::info!("Using surface format {format:?}");
+
+        let present_mode = present_mode(user_config, &surface.get_supported_present_modes(&adapter));
+        log::info!("Using present mode {present_mode:?}");
+
+        let alpha_mode = alpha_mode(user_config.transparent, &surface.get_supported_alpha_modes(&adapter));
+        Ok(Self { surface, adapter, device, queue, format, present_mode, alpha_mode, })
+    }
+}
+
+/// Ways a frame can fail that [`State`] can't deal with by itself.
+#[derive(Debug, Clone, Copy)]
+enum FrameFailure {
+    /// The surface stayed lost after reconfiguring it, so the device is likely gone too.
+    DeviceLost,
+    OutOfMemory,
+}
+
+/// The next attempt at recovering from device loss, see [`State::recover`].
+#[derive(Debug, Clone, Copy)]
+struct Recovery {
+    attempt: u32,
+    at: Instant,
+}
+
+pub struct State {
+    user_config: Config,
+    window: Arc<Window>,
+    /// Ahead of the surface, so it's dropped before it.
+    pending_frame: Option<PendingFrame>,
+    surface: wgpu::Surface,
+    config: wgpu::SurfaceConfiguration,
+    scene_format: wgpu::TextureFormat,
+    adapter: wgpu::Adapter,
+    adapter_info: wgpu::AdapterInfo,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    shader: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    /// Drawn instead of the triangle if set, with a pipeline matching its vertex layout.
+    mesh: Option<(Mesh, MeshPipelines)>,
+    mesh_views: ViewUniforms,
+    mesh_draws: MeshDraws,
+    mesh_pipeline_layout: wgpu::PipelineLayout,
+    light: Light,
+    blit: Blit,
+    target: FrameTarget,
+    offscreen_depth: Option<DepthTarget>,
+    offscreen_msaa: Option<MsaaTarget>,
+    render_scale: f32,
+    adaptive: Option<AdaptiveResolution>,
+    bloom: Option<Bloom>,
+    accumulation: Option<Accumulation>,
+    /// View-projections the accumulated frames were rendered with.
+    accumulated_views: Vec<glam::Mat4>,
+    loader: Loader,
+    depth_clear: f32,
+    camera: Camera,
+    viewport: Viewport,
+    views: Vec<(Viewport, Camera)>,
+    pipelines: Pipelines,
+    passes: Passes,
+    uniforms: Uniforms,
+    grid: Option<Grid>,
+    debug_lines: DebugLines,
+    particles: ParticleBatch,
+    sprites: SpriteBatch,
+    shapes: Shapes,
+    debug_text: DebugText,
+    scissors: Vec<Rect>,
+    recorder: Option<Recorder>,
+    input: Input,
+    proxy: EventLoopProxy<UserEvent>,
+    texture_layout: wgpu::BindGroupLayout,
+    samplers: Samplers,
+    texture_address_modes: HashMap<TextureHandle, AddressModes>,
+    texture_bind_groups: BindGroupCache<TextureHandle>,
+    uploader: Uploader,
+    lost_frames: u32,
+    failure: Option<FrameFailure>,
+    /// Set from losing the device until it's recovered, during which nothing is rendered.
+    recovery: Option<Recovery>,
+    last_present: Option<Instant>,
+    frame_times: FrameTimes,
+    /// Counted up until the frame ends, when they're moved into `frame_stats`. Includes offscreen renders in
+    /// between frames.
+    pending_stats: FrameStats,
+    frame_stats: FrameStats,
+    cursor_grab: CursorGrabMode,
+    focused: bool,
+    occluded: bool,
+    /// Of the monitor the window was last seen on, in hertz.
+    refresh_rate: Option<f32>,
+    /// Events handled since the last frame or since events started coming in, whichever was later, and when that was.
+    drained_events: usize,
+    drain_start: Instant,
+    /// Set until the window has a size that can be trusted, deferring the first frame until then.
+    awaiting_size: bool,
+    /// When [`ControlFlowPolicy::WaitUntil`] requests the next frame.
+    next_frame: Option<Instant>,
+    #[cfg(feature = "audio")]
+    audio: Option<Audio>,
+    #[cfg(feature = "overlay")]
+    overlay: bool,
+    /// Spent adding the overlay to the frame being rendered, left out of its frame time.
+    #[cfg(feature = "overlay")]
+    overlay_time: Duration,
+    /// `None` without timestamp queries, in which case the overlay has no GPU time to show.
+    #[cfg(feature = "overlay")]
+    gpu_timer: Option<GpuTimer>,
+}
+
+impl State {
+    /// Consecutive lost frames tolerated before assuming the device itself is gone.
+    const MAX_LOST_FRAMES: u32 = 3;
+    const MAX_RECOVERIES: u32 = 4;
+    const RECOVERY_BACKOFF: Duration = Duration::from_millis(250);
+
+    fn new(
+        event_loop: &EventLoopWindowTarget<UserEvent>, proxy: EventLoopProxy<UserEvent>, user_config: Config,
+    ) -> Result<Self, AndError> {
+        let mut builder = WindowBuilder::new();
+        if let Some(position) = startup_position(event_loop, &user_config) {
+            builder = builder.with_position(position);
+        }
+        if let Some(size) = user_config.size {
+            builder = builder.with_inner_size(snap_to_increments(size, user_config.resize_increments));
+        }
+        if let Some(increments) = user_config.resize_increments {
+            builder = with_resize_increments(builder, event_loop, &user_config, increments);
+        }
+        if let Some(fullscreen) = startup_fullscreen(event_loop, &user_config) {
+            builder = builder.with_fullscreen(Some(fullscreen));
+        }
+
+        let window = builder
+            .with_title("And".to_string())
+            .with_resizable(user_config.resize_increments.is_some())
+            .with_window_icon(user_config.load_icon.then(window_icon).flatten())
+            .with_decorations(user_config.decorations)
+            .with_always_on_top(user_config.always_on_top)
+            .with_transparent(user_config.transparent)
+            .build(event_loop)
+            .expect("Unable to create window");
+        let window = Arc::new(window);
+
+        let gpu = Gpu::new(&window, &user_config)?;
+        let mut st = Self::with_gpu(window, proxy, user_config, gpu);
+        // Android reports a size before the native window is laid out, which may be wrong until the first resize.
+        st.awaiting_size |= cfg!(target_os = "android");
+        st.open_audio();
+        Ok(st)
+    }
+
+    /// Builds on a window, surface and device the host already owns, e.g. a plugin inside a larger engine, instead
+    /// of creating them. `adapter` is the one `device` was requested from, and `surface` has to be created for
+    /// `window` from the same instance. The state configures `surface` from then on, including on every resize, so
+    /// the host shouldn't configure it itself.
+    ///
+    /// The window and device stay shared with the host, which can keep using them, e.g. to create its own
+    /// resources; the state only holds on to them until it's dropped. The host's event loop has to carry
+    /// [`UserEvent`]s, which texture loading and [`State::event_loop_proxy`] wake it with, and feed them to
+    /// [`State::handle_event`] along with its window's events. Device loss isn't recovered from, since that would
+    /// mean replacing the host's device. Fails with [`AndError::NoAdapter`] if `adapter` can't present to `surface`,
+    /// or as per [`Config::surface_format`].
+    pub fn from_parts(
+        window: Arc<Window>, proxy: EventLoopProxy<UserEvent>, adapter: wgpu::Adapter, device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>, surface: wgpu::Surface, user_config: Config,
+    ) -> Result<Self, AndError> {
+        if let Some(reason) = adapter_rejection(&adapter, &surface) {
+            log::error!("Adapter {:?} can't present to the surface: {reason}", adapter.get_info().name);
+            return Err(AndError::NoAdapter);
+        }
+
+        let gpu = Gpu::with_device(surface, adapter, device, queue, &user_config)?;
+        let mut st = Self::with_gpu(window, proxy, user_config, gpu);
+        st.open_audio();
+        if !st.awaiting_size {
+            st.window.request_redraw();
+        }
+        Ok(st)
+    }
+
+    #[cfg(feature = "audio")]
+    fn open_audio(&mut self) {
+        self.audio = Audio::new()
+            .map_err(|e| log::warn!("Audio is disabled: {e}"))
+            .ok();
+    }
+
+    #[cfg(not(feature = "audio"))]
+    fn open_audio(&mut self) {}
+
+    fn with_gpu(window: Arc<Window>, proxy: EventLoopProxy<UserEvent>, mut user_config: Config, gpu: Gpu) -> Self {
+        let Gpu { surface, adapter, device, queue, format, present_mode, alpha_mode, } = gpu;
+        let PhysicalSize { width, height, } = window.inner_size();
+        let awaiting_size = width == 0 || height == 0;
+        let refresh_rate = refresh_rate(&window);
+        // Surfaces can't be configured empty; this is replaced as soon as there's a real size.
+        let (width, height) = (width.max(1), height.max(1));
+
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: {
-                let format = surface.get_supported_formats(&adapter)[0];
-                log::info!("Using surface format {format:?}");
-                format
-            },
+            format,
             width, height,
-            present_mode: wgpu::PresentMode::AutoVsync,
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            present_mode,
+            alpha_mode,
         };
         surface.configure(&device, &config);
         let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
@@ -96,139 +1233,2360 @@ impl State {
             bind_group_layouts: &[],
             push_constant_ranges: &[],
         });
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
+        let scene_format = scene_format(&user_config, config.format, &adapter);
+        fallback_msaa(&mut user_config, &adapter, scene_format);
+        let samples = user_config.msaa_samples;
+        let pipeline = scene_pipeline(
+            &device, &pipeline_layout, triangle_vertex(&shader), "fs_main", false, scene_format, &user_config,
+        );
+        let mesh_views = ViewUniforms::new(&device, "Mesh uniform");
+        let mesh_draws = MeshDraws::new(&device);
+        let light = Light::new(&device, user_config.light);
+        let mesh_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Mesh pipeline layout"),
+            bind_group_layouts: &[&mesh_views.layout, &mesh_draws.layout, &light.layout],
+            push_constant_ranges: &[],
         });
 
-        Self { window, surface, config, device, queue, pipeline, }
+        let blit = Blit::new(
+            &device, config.format, user_config.tonemap, encode_srgb(scene_format, config.format), config.alpha_mode,
+        );
+        let depth_format = target::depth_format(user_config.stencil.is_some());
+        let target = FrameTarget::new(&device, &blit, scene_format, depth_format, samples, width, height);
+        let bloom = user_config.bloom
+            .map(|settings| Bloom::new(&device, scene_format, settings, &target.view, width, height));
+        let accumulation = (user_config.accumulation_frames > 0)
+            .then(|| Accumulation::new(&device, &blit, scene_format, user_config.accumulation_frames, width, height));
+        let loader = Loader::new(device.clone(), queue.clone(), proxy.clone());
+        let debug_lines = DebugLines::new(&device, scene_format, depth_format, samples, user_config.reverse_z);
+        let grid = user_config.grid
+            .map(|settings| Grid::new(&device, scene_format, depth_format, samples, user_config.reverse_z, settings));
+        let particles = ParticleBatch::new(&device, scene_format, depth_format, samples, user_config.reverse_z);
+        let texture_layout = target::texture_layout(&device, "Texture bind group layout");
+        let sprites = SpriteBatch::new(&device, scene_format, depth_format, samples, &texture_layout);
+        let shapes = Shapes::new(&device, scene_format, depth_format, samples);
+        let debug_text = DebugText::new(&device, &queue, scene_format, depth_format, samples);
+        let adaptive = user_config.adaptive_resolution.map(AdaptiveResolution::new);
+        let frame_time_history = user_config.frame_time_history;
+        let frames_in_flight = user_config.frames_in_flight;
+
+        let adapter_info = adapter.get_info();
+        log::info!("Using adapter {:?} on the {:?} backend", adapter_info.name, adapter_info.backend);
+        #[cfg(feature = "overlay")]
+        let gpu_timer = GpuTimer::new(&device, &queue);
+
+        let depth_clear = if user_config.reverse_z { 0.0 } else { 1.0 };
+        let mut st = Self {
+            pending_frame: None,
+            user_config, window, surface, config, scene_format, adapter, adapter_info, device, queue, shader,
+            pipeline_layout, pipeline, blit, target, bloom, loader,
+            depth_clear,
+            camera: Camera::default(),
+            viewport: Viewport::default(),
+            views: Vec::new(),
+            mesh: None,
+            mesh_views,
+            mesh_draws,
+            mesh_pipeline_layout,
+            light,
+            pipelines: Pipelines::default(),
+            passes: Passes::default(),
+            uniforms: Uniforms::new(frames_in_flight),
+            grid,
+            debug_lines,
+            particles,
+            sprites,
+            shapes,
+            debug_text,
+            scissors: Vec::new(),
+            offscreen_depth: None,
+            offscreen_msaa: None,
+            render_scale: 1.0,
+            adaptive,
+            accumulation,
+            accumulated_views: Vec::new(),
+            recorder: None,
+            input: Input::default(),
+            proxy,
+            texture_layout,
+            samplers: Samplers::new(),
+            texture_address_modes: HashMap::new(),
+            texture_bind_groups: BindGroupCache::new(),
+            uploader: Uploader::new(),
+            lost_frames: 0,
+            failure: None,
+            recovery: None,
+            last_present: None,
+            frame_times: FrameTimes::new(frame_time_history),
+            pending_stats: FrameStats::default(),
+            frame_stats: FrameStats::default(),
+            cursor_grab: CursorGrabMode::None,
+            focused: true,
+            occluded: false,
+            refresh_rate,
+            drained_events: 0,
+            awaiting_size,
+            drain_start: Instant::now(),
+            next_frame: None,
+            #[cfg(feature = "audio")]
+            audio: None,
+            #[cfg(feature = "overlay")]
+            overlay: false,
+            #[cfg(feature = "overlay")]
+            overlay_time: Duration::ZERO,
+            #[cfg(feature = "overlay")]
+            gpu_timer,
+        };
+
+        st.update_adjustment();
+        if st.user_config.prewarm {
+            st.prewarm();
+        }
+        st
     }
-}
 
-pub fn run() {
-    #[cfg(target_os = "android")]
-    android_logger::init_once(android_logger::Config::default()
-        .with_min_level(log::Level::Info)
-    );
+    /// Renders a frame nobody sees through the scene, bloom and presenting passes, and waits for it to finish.
+    /// Pipelines are all built up front already, but some drivers (GL ones especially) only compile shaders once
+    /// they're first drawn with, which would otherwise stall the first real frame. Overlays with nothing to draw are
+    /// skipped; [`Config::on_frame`] isn't invoked.
+    pub fn prewarm(&mut self) {
+        let start = Instant::now();
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Prewarm renderer"),
+        });
 
-    #[cfg(not(target_os = "android"))]
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
+        let target = &self.target;
+        self.draw_scene(&mut encoder, &target.view, target.msaa.as_ref(), &target.depth, None);
+        if let Some(bloom) = self.bloom.as_ref() {
+            bloom.draw(&mut encoder, &self.target.view);
+        }
 
-    let event_loop = EventLoop::new();
-    let mut state = None;
+        // Presenting goes into a scratch texture instead of the surface, which would have to actually be presented.
+        let scratch = resources::create_texture(&self.device, &wgpu::TextureDescriptor {
+            label: Some("Prewarm scratch"),
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1, },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+        let scratch_view = scratch.create_view(&wgpu::TextureViewDescriptor::default());
+        self.blit.draw(&mut encoder, &self.target.bind_group, &scratch_view, self.content_rect());
 
-    event_loop.run(move |event, event_loop, control_flow| {
-        *control_flow = ControlFlow::Wait;
-        match event {
-            Event::NewEvents(StartCause::Init) => log::info!("Hello, world!"),
-            Event::Resumed => {
-                log::info!("Hello again, world!");
-                if state.is_none() {
-                    let st = State::new(&event_loop);
-                    st.window.request_redraw();
-                    state = Some(st);
-                }
-            },
-            Event::Suspended => {
-                log::info!("Where are you going, world?");
-                state = None;
-            },
-            Event::WindowEvent { window_id, event } => {
-                let Some(st) = state.as_mut() else { return };
-                if window_id != st.window.id() { return };
+        self.queue.submit(iter::once(encoder.finish()));
+        self.device.poll(wgpu::Maintain::Wait);
+        log::info!("Prewarmed in {:?}", start.elapsed());
+    }
 
-                match event {
-                    WindowEvent::Resized(PhysicalSize { width, height, }) => {
-                        if width == 0 || height == 0 { return };
+    /// Schedules the first attempt at recovering from device loss, stopping rendering until one succeeds.
+    fn lose_device(&mut self) {
+        self.recovery = Some(Recovery { attempt: 0, at: Instant::now() + Self::RECOVERY_BACKOFF, });
+    }
 
-                        st.config.width = width;
-                        st.config.height = height;
-                        st.surface.configure(&st.device, &st.config);
-                    },
-                    WindowEvent::CloseRequested => *control_flow = ControlFlow::ExitWithCode(0),
-                    _ => {},
-                }
+    fn recovery_due(&self) -> bool {
+        self.recovery.is_some_and(|recovery| Instant::now() >= recovery.at)
+    }
+
+    /// Rebuilds everything GPU-side on a fresh adapter and device, keeping the window, configuration, camera,
+    /// viewports, cursor grab and input state, and rebuilding registered pipelines and uniforms. A device that just
+    /// went away (driver reset, GPU switch) may take a moment to come back, so a failed attempt schedules the next
+    /// one with exponential backoff, which [`State::next_control_flow`] waits for; `None` once they're used up.
+    ///
+    /// The mesh, loaded textures and the render scale are lost, leaving every [`TextureHandle`] dangling, and
+    /// recording stops. [`Config::on_device_recovered`] is invoked afterwards to restore them.
+    fn recover(mut self) -> Option<Self> {
+        let Recovery { attempt, .. } = self.recovery.expect("Scheduled by losing the device");
+        let gpu = match Gpu::new(&self.window, &self.user_config) {
+            Ok(gpu) => gpu,
+            Err(e) => {
+                log::warn!("Recovery attempt {} of {} failed: {e}", attempt + 1, Self::MAX_RECOVERIES);
+                if attempt + 1 >= Self::MAX_RECOVERIES { return None };
+
+                let at = Instant::now() + Self::RECOVERY_BACKOFF * 2u32.pow(attempt + 1);
+                self.recovery = Some(Recovery { attempt: attempt + 1, at, });
+                return Some(self);
             },
-            Event::RedrawRequested(window_id) => {
-                let Some(st) = state.as_ref() else { return };
-                if window_id != st.window.id() { return };
+        };
 
-                let output = match st.surface.get_current_texture() {
-                    Ok(output) => output,
-                    Err(err) => {
-                        match err {
-                            wgpu::SurfaceError::Lost => st.surface.configure(&st.device, &st.config),
-                            wgpu::SurfaceError::OutOfMemory => *control_flow = ControlFlow::ExitWithCode(1),
-                            e => log::error!("Skipping frame due to {e:?}"),
-                        }
+        let Self {
+            window, user_config, proxy, camera, viewport, views, input, depth_clear, cursor_grab, pipelines, passes,
+            mut uniforms,
+            #[cfg(feature = "audio")]
+            audio,
+            ..
+        } = self;
 
-                        return;
-                    },
-                };
+        uniforms.recreate(&gpu.device, user_config.frames_in_flight);
+        self = Self {
+            camera, viewport, views, input, depth_clear, cursor_grab, pipelines, passes, uniforms,
+            #[cfg(feature = "audio")]
+            audio,
+            ..Self::with_gpu(window, proxy, user_config, gpu)
+        };
+        self.rebuild_registered_pipelines();
+        log::info!("Recovered from device loss");
 
-                let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
-                let mut encoder = st.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("Screen renderer"),
-                });
-
-                {
-                    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                        label: Some("Screen pass"),
-                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                            view: &view,
-                            resolve_target: None,
-                            ops: wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.1, g: 0.2, b: 0.3, a: 1.0, }),
-                                store: true,
-                            },
-                        })],
-                        depth_stencil_attachment: None,
-                    });
-
-                    pass.set_pipeline(&st.pipeline);
-                    pass.draw(0..3, 0..1);
-                }
+        if let Some(mut on_device_recovered) = self.user_config.on_device_recovered.take() {
+            on_device_recovered(&mut self);
+            self.user_config.on_device_recovered = Some(on_device_recovered);
+        }
+        self.window.request_redraw();
+        Some(self)
+    }
 
-                st.queue.submit(iter::once(encoder.finish()));
-                output.present();
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let start = Instant::now();
+        self.poll_textures();
+        if let Some(mut on_frame) = self.user_config.on_frame.take() {
+            on_frame(self);
+            self.user_config.on_frame = Some(on_frame);
+        }
+
+        // Only one surface texture can be out at a time.
+        self.flush_pending_frame();
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            Err(e) => {
+                // Otherwise this frame's draws would pile onto the next one's.
+                self.clear_immediate();
+                return Err(e);
             },
-            Event::LoopDestroyed => {
-                state = None;
-                log::info!("Goodbye, world!");
+        };
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Screen renderer"),
+        });
+        #[cfg(feature = "overlay")]
+        if let Some(timer) = self.gpu_timer.as_mut().filter(|_| self.overlay) {
+            timer.begin(&self.device, &mut encoder);
+        }
+
+        let recorded = match self.user_config.on_render_frame.take() {
+            Some(mut on_render_frame) => {
+                on_render_frame(&mut encoder, &view);
+                self.user_config.on_render_frame = Some(on_render_frame);
+                None
             },
-            _ => {},
+            None => self.draw_frame(&mut encoder, &view),
+        };
+
+        #[cfg(feature = "overlay")]
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.end(&mut encoder);
         }
-    });
+        self.uploader.finish();
+        self.queue.submit(iter::once(encoder.finish()));
+        self.pending_stats.command_buffers += 1;
+        self.uploader.recall();
+        #[cfg(feature = "overlay")]
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.submitted();
+        }
+        self.uniforms.advance(&self.queue);
+        if let Some(mut on_post_submit) = self.user_config.on_post_submit.take() {
+            on_post_submit(&self.device, &self.queue);
+            self.user_config.on_post_submit = Some(on_post_submit);
+        }
+
+        if self.user_config.defer_present {
+            self.pending_frame = Some(PendingFrame { output, });
+        } else {
+            output.present();
+            self.last_present = Some(Instant::now());
+        }
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Some(readback) = recorded {
+                recorder.push(readback);
+            }
+            if recorder.is_done() {
+                log::info!("Finished recording frames");
+                self.recorder = None;
+            } else {
+                self.window.request_redraw();
+            }
+        }
+
+        // Nothing else wakes the loop up between frames; without this, Immediate would never get to tear.
+        if self.config.present_mode == wgpu::PresentMode::Immediate {
+            self.window.request_redraw();
+        }
+
+        self.clear_immediate();
+        self.input.end_frame();
+
+        self.drained_events = 0;
+        self.drain_start = Instant::now();
+        self.frame_stats = std::mem::take(&mut self.pending_stats);
+
+        // Measured within the frame rather than between frames, so time spent idle between redraws doesn't count.
+        let frame_time = start.elapsed();
+        #[cfg(feature = "overlay")]
+        let frame_time = frame_time.saturating_sub(std::mem::take(&mut self.overlay_time));
+        self.frame_times.push(frame_time.as_secs_f32() * 1000.0);
+        let adapted = self.adaptive.as_mut().and_then(|adaptive| adaptive.update(frame_time, self.render_scale));
+        if let Some(scale) = adapted {
+            log::debug!("Adapting render scale to {scale} after a {frame_time:?} frame");
+            self.set_render_scale(scale);
+        }
+
+        Ok(())
+    }
+
+    /// Drops everything queued for the frame, whether or not it was rendered.
+    fn clear_immediate(&mut self) {
+        self.debug_lines.clear();
+        self.mesh_draws.clear();
+        self.particles.clear();
+        self.sprites.clear();
+        self.shapes.clear();
+        self.debug_text.clear();
+        if !self.scissors.is_empty() {
+            log::warn!("{} scissor rects were left pushed at the end of the frame", self.scissors.len());
+            self.scissors.clear();
+        }
+    }
+
+    /// Records the crate's own frame into `encoder`, ending with the blit to `view`. Returns the frame to record,
+    /// if the recorder wants one.
+    fn draw_frame(&mut self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) -> Option<Readback> {
+        let (width, height) = self.content_size();
+        let view_projs = self.view_projs(width, height);
+        self.debug_lines.prepare(&self.device, encoder, &mut self.uploader, &view_projs);
+        if self.mesh.is_some() {
+            self.mesh_views.write(&self.device, encoder, &mut self.uploader, &view_projs);
+            self.mesh_draws.prepare(&self.device, encoder, &mut self.uploader);
+        }
+        self.prepare_grid(encoder, &view_projs);
+        self.prepare_particles(encoder, &view_projs);
+        self.prepare_sprites(encoder, width, height);
+        if let Some(crosshair) = self.user_config.crosshair {
+            self.shapes.crosshair(glam::Vec2::new(width as f32, height as f32) * 0.5, crosshair);
+        }
+        #[cfg(feature = "overlay")]
+        if self.overlay {
+            let start = Instant::now();
+            let scale = self.window.scale_factor() as f32;
+            let gpu_time = self.gpu_timer.as_ref().and_then(GpuTimer::last);
+            overlay::frame_time_graph(
+                &mut self.shapes, &mut self.debug_text, self.frame_times.as_slice(), gpu_time, &self.adapter_info,
+                scale,
+            );
+            self.overlay_time = start.elapsed();
+        }
+        self.shapes.prepare(&self.device, encoder, &mut self.uploader, width, height);
+        self.debug_text.prepare(&self.device, encoder, &mut self.uploader, width, height);
+        if let Some(mut on_pre_render) = self.user_config.on_pre_render.take() {
+            on_pre_render(encoder);
+            self.user_config.on_pre_render = Some(on_pre_render);
+        }
+
+        let mut on_render = self.user_config.on_render.take();
+        let target = &self.target;
+        self.draw_scene(encoder, &target.view, target.msaa.as_ref(), &target.depth, on_render.as_mut());
+        self.user_config.on_render = on_render;
+        let mut passes = std::mem::take(&mut self.passes);
+        let target = &self.target;
+        self.draw_passes(&mut passes, encoder, &target.view, target.msaa.as_ref(), &target.depth);
+        self.passes = passes;
+        self.pending_stats.render_passes += 1 + self.passes.len() as u32;
+        if let Some(bloom) = self.bloom.as_ref() {
+            self.pending_stats.render_passes += bloom.draw(encoder, &self.target.view);
+        }
+
+        if let Some(accumulation) = self.accumulation.as_mut() {
+            if view_projs != self.accumulated_views {
+                accumulation.reset();
+                self.accumulated_views = view_projs;
+            }
+            if accumulation.accumulate(encoder, &self.target.bind_group) {
+                self.pending_stats.render_passes += 1;
+            }
+        }
+
+        let (target_width, target_height) = (self.target.width, self.target.height);
+        let recorded = self.recorder.as_ref()
+            .filter(|recorder| recorder.wants_frame())
+            .map(|_| Readback::new(&self.device, encoder, &self.target.texture, (0, 0), target_width, target_height));
+
+        let presented = self.accumulation.as_ref().map_or(&self.target.bind_group, Accumulation::bind_group);
+        self.blit.draw(encoder, presented, view, self.content_rect());
+        self.pending_stats.render_passes += 1;
+        recorded
+    }
+
+    /// Takes the last frame out to present it later, if [`Config::defer_present`] is set and it hasn't been presented
+    /// yet. It has to be presented or dropped before the next frame is rendered; frames that aren't taken by then
+    /// are presented right before it.
+    pub fn take_pending_frame(&mut self) -> Option<PendingFrame> {
+        self.pending_frame.take()
+    }
+
+    /// Presents a frame taken through [`State::take_pending_frame`].
+    pub fn present(&mut self, frame: PendingFrame) {
+        frame.present();
+        self.last_present = Some(Instant::now());
+    }
+
+    fn flush_pending_frame(&mut self) {
+        if let Some(frame) = self.pending_frame.take() {
+            self.present(frame);
+        }
+    }
+
+    /// Applies the surface configuration, presenting a pending frame first since its texture belongs to the old one.
+    fn configure_surface(&mut self) {
+        self.flush_pending_frame();
+        self.surface.configure(&self.device, &self.config);
+    }
+
+    /// Renders and presents a frame, reconfiguring the surface if it got lost and skipping frames that time out.
+    /// Nothing is rendered until the window has a trustworthy size.
+    fn present_frame(&mut self) -> Result<(), FrameFailure> {
+        if self.awaiting_size || self.occluded { return Ok(()) };
+
+        match self.render() {
+            Ok(()) => self.lost_frames = 0,
+            Err(wgpu::SurfaceError::Lost) if self.lost_frames < Self::MAX_LOST_FRAMES => {
+                self.lost_frames += 1;
+                self.configure_surface();
+            },
+            Err(wgpu::SurfaceError::Lost) => return Err(FrameFailure::DeviceLost),
+            Err(wgpu::SurfaceError::OutOfMemory) => return Err(FrameFailure::OutOfMemory),
+            Err(e) => log::error!("Skipping frame due to {e:?}"),
+        }
+
+        Ok(())
+    }
+
+    /// Renders and presents a frame right away instead of waiting for a redraw request, e.g. from
+    /// [`Config::on_key`] in an app that only repaints on input. Surface errors are handled like in the event loop;
+    /// device loss is deferred to it, since recovering rebuilds the whole [`State`]. Don't call this from
+    /// [`Config::on_frame`], which it invokes.
+    pub fn render_now(&mut self) {
+        if self.recovery.is_some() { return };
+
+        if let Err(failure) = self.present_frame() {
+            self.failure = Some(failure);
+            self.window.request_redraw();
+        }
+    }
+
+    /// Counts a handled event against [`Config::event_budget`], rendering a frame right away once it's used up.
+    fn count_event(&mut self) {
+        let Some(budget) = self.user_config.event_budget else { return };
+
+        self.drained_events += 1;
+        if self.drained_events < budget.max_events && self.drain_start.elapsed() < budget.max_time { return };
+
+        log::debug!("Rendering after {} events in {:?}", self.drained_events, self.drain_start.elapsed());
+        self.render_now();
+        // Also reset here in case rendering bailed out early.
+        self.drained_events = 0;
+        self.drain_start = Instant::now();
+    }
+
+    /// Renders the scene into a caller-owned texture instead of the surface, e.g. to composite it in another wgpu
+    /// renderer. `target` must have the scene's format (see [`State::scene_format`]) and `RENDER_ATTACHMENT`
+    /// usage, and be `width` by `height` large.
+    pub fn render_to_texture(&mut self, target: &wgpu::TextureView, width: u32, height: u32) {
+        self.poll_textures();
+
+        let (format, depth_format, samples) = (self.scene_format, self.depth_format(), self.user_config.msaa_samples);
+        let stale = |depth: &DepthTarget| {
+            (depth.format, depth.sample_count, depth.width, depth.height) != (depth_format, samples, width, height)
+        };
+        if self.offscreen_depth.as_ref().is_none_or(stale) {
+            // Replaced rather than cached per size, so rendering at varying sizes doesn't pile up depth buffers.
+            self.offscreen_depth = Some(DepthTarget::new(&self.device, depth_format, samples, width, height));
+        }
+        let stale = |msaa: &MsaaTarget| {
+            (msaa.format, msaa.sample_count, msaa.width, msaa.height) != (format, samples, width, height)
+        };
+        if self.offscreen_msaa.as_ref().is_none_or(stale) {
+            self.offscreen_msaa = MsaaTarget::new(&self.device, format, samples, width, height);
+        }
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Texture renderer"),
+        });
+
+        let view_projs = self.view_projs(width, height);
+        self.debug_lines.prepare(&self.device, &mut encoder, &mut self.uploader, &view_projs);
+        if self.mesh.is_some() {
+            self.mesh_views.write(&self.device, &mut encoder, &mut self.uploader, &view_projs);
+            self.mesh_draws.prepare(&self.device, &mut encoder, &mut self.uploader);
+        }
+        self.prepare_grid(&mut encoder, &view_projs);
+        self.prepare_particles(&mut encoder, &view_projs);
+        self.prepare_sprites(&mut encoder, width, height);
+        self.shapes.prepare(&self.device, &mut encoder, &mut self.uploader, width, height);
+        self.debug_text.prepare(&self.device, &mut encoder, &mut self.uploader, width, height);
+
+        let depth = self.offscreen_depth.as_ref().expect("Created above");
+        let mut on_render = self.user_config.on_render.take();
+        let msaa = self.offscreen_msaa.as_ref();
+        self.draw_scene(&mut encoder, target, msaa, depth, on_render.as_mut());
+        self.user_config.on_render = on_render;
+        let mut passes = std::mem::take(&mut self.passes);
+        self.draw_passes(&mut passes, &mut encoder, target, msaa, depth);
+        self.passes = passes;
+        self.uploader.finish();
+        self.queue.submit(iter::once(encoder.finish()));
+        self.uploader.recall();
+        self.uniforms.advance(&self.queue);
+        self.pending_stats.render_passes += 1 + self.passes.len() as u32;
+        self.pending_stats.command_buffers += 1;
+    }
+
+    /// Draws into `msaa` and resolves it into `color` if set, or into `color` directly. All of them are the size of
+    /// `depth`.
+    fn draw_scene(
+        &self, encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView, msaa: Option<&MsaaTarget>, depth: &DepthTarget,
+        on_render: Option<&mut RenderCallback>,
+    ) {
+        let (width, height) = (depth.width, depth.height);
+        let first_pass = depth.first_pass();
+        let depth_load = if self.user_config.clear_depth {
+            wgpu::LoadOp::Clear(self.depth_clear)
+        } else {
+            if first_pass {
+                log::warn!("Loading depth that nothing was rendered into yet, the depth test will see zeroes");
+            }
+            wgpu::LoadOp::Load
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Scene pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: msaa.map_or(color, |msaa| &msaa.view),
+                resolve_target: msaa.map(|_| color),
+                ops: wgpu::Operations {
+                    load: self.color_load(true, None),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: depth_load,
+                    store: true,
+                }),
+                stencil_ops: self.user_config.stencil.map(|stencil| wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(stencil.clear),
+                    store: true,
+                }),
+            }),
+        });
+
+        if let Some(stencil) = self.user_config.stencil {
+            pass.set_stencil_reference(stencil.reference);
+        }
+        pass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+        self.shapes.draw_masks(&mut pass);
+        let cameras = iter::once(&self.camera).chain(self.views.iter().map(|(_, camera)| camera));
+        for (i, (viewport, camera)) in self.viewports().zip(cameras).enumerate() {
+            viewport.apply(&mut pass, width, height);
+            match self.mesh.as_ref() {
+                Some((mesh, pipelines)) => {
+                    self.mesh_views.bind(&mut pass, 0, i);
+                    self.light.bind(&mut pass, 2);
+                    self.mesh_draws.draw(&mut pass, mesh, pipelines, camera.eye, self.user_config.sort_transparency);
+                },
+                None => {
+                    pass.set_pipeline(&self.pipeline);
+                    pass.draw(0..3, 0..1);
+                },
+            }
+            if let Some(grid) = self.grid.as_ref() {
+                grid.draw(&mut pass, i);
+            }
+            self.particles.draw(&mut pass, i);
+            self.debug_lines.draw(&mut pass, i);
+        }
+
+        // Overlays are in screen space, across the whole frame.
+        pass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+        if let Some(on_render) = on_render {
+            let context = RenderContext { pipelines: &self.pipelines, uniforms: &self.uniforms, width, height, };
+            on_render(&mut pass, &context);
+        }
+        self.sprites.draw(&mut pass, width, height, |texture| self.texture_bind_groups.get(&texture));
+        self.shapes.draw(&mut pass, width, height);
+        self.debug_text.draw(&mut pass);
+    }
+
+    /// Draws every registered pass on top of the scene pass, into the same attachments.
+    fn draw_passes(
+        &self, passes: &mut Passes, encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView, msaa: Option<&MsaaTarget>, depth: &DepthTarget,
+    ) {
+        let context = RenderContext {
+            pipelines: &self.pipelines, uniforms: &self.uniforms, width: depth.width, height: depth.height,
+        };
+        for (name, load, draw) in passes.iter_mut() {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(name),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: msaa.map_or(color, |msaa| &msaa.view),
+                    resolve_target: msaa.map(|_| color),
+                    ops: wgpu::Operations {
+                        load: self.color_load(false, load),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }),
+                    stencil_ops: self.user_config.stencil.map(|_| wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }),
+                }),
+            });
+
+            if let Some(stencil) = self.user_config.stencil {
+                pass.set_stencil_reference(stencil.reference);
+            }
+            draw(&mut pass, &context);
+        }
+    }
+
+    /// What a pass drawing into the scene's color attachment loads: `requested` if it asked for something, or else
+    /// [`Config::clear_color`] as per [`Config::clear`] if it's the frame's first pass, and what earlier passes left
+    /// if it isn't, so later passes can't wipe them by accident.
+    fn color_load(&self, first: bool, requested: Option<wgpu::LoadOp<wgpu::Color>>) -> wgpu::LoadOp<wgpu::Color> {
+        match requested {
+            Some(load) => load,
+            None if first && self.user_config.clear => wgpu::LoadOp::Clear(self.clear_color()),
+            None => wgpu::LoadOp::Load,
+        }
+    }
+
+    fn prepare_grid(&mut self, encoder: &mut wgpu::CommandEncoder, view_projs: &[glam::Mat4]) {
+        let Some(grid) = self.grid.as_mut() else { return };
+
+        let eyes = iter::once(self.camera.eye).chain(self.views.iter().map(|(_, camera)| camera.eye));
+        let views = view_projs.iter().copied().zip(eyes).collect::<Vec<_>>();
+        grid.prepare(&self.device, encoder, &mut self.uploader, &views);
+    }
+
+    fn prepare_particles(&mut self, encoder: &mut wgpu::CommandEncoder, view_projs: &[glam::Mat4]) {
+        let cameras = iter::once(&self.camera).chain(self.views.iter().map(|(_, camera)| camera));
+        let views = view_projs.iter().zip(cameras)
+            .map(|(&view_proj, camera)| (view_proj, camera.eye, camera.up))
+            .collect::<Vec<_>>();
+        self.particles.prepare(&self.device, encoder, &mut self.uploader, &views);
+    }
+
+    /// Turns the ground grid on, off, or changes its settings in place.
+    pub fn set_grid(&mut self, grid: Option<GridSettings>) {
+        self.user_config.grid = grid;
+        match (self.grid.as_mut(), grid) {
+            (Some(pass), Some(settings)) => pass.settings = settings,
+            _ => self.rebuild_grid(),
+        }
+    }
+
+    fn rebuild_grid(&mut self) {
+        let (format, depth_format, reverse_z) = (self.scene_format, self.depth_format(), self.user_config.reverse_z);
+        let samples = self.user_config.msaa_samples;
+        self.grid = self.user_config.grid
+            .map(|settings| Grid::new(&self.device, format, depth_format, samples, reverse_z, settings));
+    }
+
+    /// Viewports of the main camera and then every extra view, in drawing order.
+    fn viewports(&self) -> impl Iterator<Item = Viewport> + '_ {
+        iter::once(self.viewport).chain(self.views.iter().map(|(viewport, _)| *viewport))
+    }
+
+    /// View-projections of every view for a `width` by `height` frame, in the same order as [`State::viewports`].
+    fn view_projs(&self, width: u32, height: u32) -> Vec<glam::Mat4> {
+        iter::once(self.view_proj_for(width, height))
+            .chain(self.views.iter().map(|(viewport, camera)| {
+                camera.view_proj(viewport.aspect(width, height), self.user_config.reverse_z)
+            }))
+            .collect()
+    }
+
+    /// Restricts the main camera's view to a sub-rectangle of the frame, given in fractions of its size, and maps
+    /// it onto `min_depth..max_depth`. Overlays such as sprites still cover the whole frame.
+    pub fn set_viewport(&mut self, x: f32, y: f32, width: f32, height: f32, min_depth: f32, max_depth: f32) {
+        self.viewport = Viewport { x, y, width, height, min_depth, max_depth, };
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    /// Extra views rendered after the main camera's, each with its own camera, e.g. for split-screen or
+    /// picture-in-picture. The whole scene is drawn once per view, debug lines included.
+    pub fn views_mut(&mut self) -> &mut Vec<(Viewport, Camera)> {
+        &mut self.views
+    }
+
+    /// [`Config::clear_color`], premultiplied if the surface expects it. Blending with `ALPHA_BLENDING` onto a
+    /// premultiplied destination keeps it premultiplied, so this is the only place that needs to care.
+    fn clear_color(&self) -> wgpu::Color {
+        surface_color(self.user_config.clear_color, self.config.alpha_mode)
+    }
+
+    fn prepare_sprites(&mut self, encoder: &mut wgpu::CommandEncoder, width: u32, height: u32) {
+        // Bind groups are looked up while the pass borrows everything immutably, so they have to exist by then.
+        let textures = self.sprites.textures().collect::<Vec<_>>();
+        for texture in textures {
+            self.texture_bind_group(texture);
+        }
+
+        let loader = &self.loader;
+        let loaded = |texture| loader.get(texture).is_some();
+        self.sprites.prepare(&self.device, encoder, &mut self.uploader, width, height, loaded);
+    }
+
+    /// Draws a sprite on top of the scene during this frame only. Sprites of textures that haven't finished loading
+    /// are skipped.
+    pub fn draw_sprite(&mut self, sprite: Sprite) {
+        let scissor = self.scissor();
+        self.sprites.push(sprite, scissor);
+    }
+
+    /// Clips sprites and shapes drawn from now on to `rect`, within whatever scissor rect is already pushed, until
+    /// the matching [`State::pop_scissor`]. The stack is emptied at the end of every frame.
+    pub fn push_scissor(&mut self, rect: Rect) {
+        let rect = match self.scissors.last() {
+            Some(&outer) => outer.intersect(rect),
+            None => rect,
+        };
+        self.scissors.push(rect);
+    }
+
+    pub fn pop_scissor(&mut self) {
+        if self.scissors.pop().is_none() {
+            log::warn!("Popped a scissor rect that was never pushed");
+        }
+    }
+
+    fn scissor(&self) -> Option<Rect> {
+        self.scissors.last().copied()
+    }
+
+    pub fn set_sprite_sort_mode(&mut self, sort_mode: SpriteSortMode) {
+        self.sprites.sort_mode = sort_mode;
+    }
+
+    /// Caps how many sprites go into a single draw call, 4096 by default.
+    pub fn set_sprite_batch_size(&mut self, max_batch: usize) {
+        self.sprites.max_batch = max_batch;
+    }
+
+    /// How many draw calls the sprites of the last frame took.
+    pub fn sprite_batch_count(&self) -> usize {
+        self.sprites.batch_count()
+    }
+
+    /// Draws a line from `a` to `b` in world space during this frame only.
+    pub fn draw_line(&mut self, a: glam::Vec3, b: glam::Vec3, color: [f32; 4]) {
+        self.debug_lines.line(a, b, color);
+    }
+
+    /// Draws the edges of the axis-aligned box spanning from `min` to `max` during this frame only.
+    pub fn draw_aabb(&mut self, min: glam::Vec3, max: glam::Vec3, color: [f32; 4]) {
+        self.debug_lines.aabb(min, max, color);
+    }
+
+    /// Draws a grid on the XZ plane through `center`, with lines every `spacing` units up to `half_extent` away,
+    /// during this frame only. Draws nothing if `spacing` isn't finite and positive, and at most 512 lines on either
+    /// side of `center` along each axis.
+    pub fn draw_grid(&mut self, center: glam::Vec3, half_extent: f32, spacing: f32, color: [f32; 4]) {
+        if !spacing.is_finite() || spacing <= 0.0 { return };
+
+        self.debug_lines.grid(center, half_extent, spacing, color);
+    }
+
+    /// Draws every live particle of `particles` into the scene from every view, during this frame only. Call
+    /// [`Particles::update`] yourself to simulate them.
+    pub fn draw_particles(&mut self, particles: &Particles) {
+        self.particles.push(particles);
+    }
+
+    /// Shows, hides or restyles the crosshair; see [`Config::crosshair`].
+    pub fn set_crosshair(&mut self, crosshair: Option<CrosshairSettings>) {
+        self.user_config.crosshair = crosshair;
+    }
+
+    /// Draws an anti-aliased rectangle with corners rounded by `radius` pixels on top of the scene, during this frame
+    /// only.
+    pub fn draw_rounded_rect(&mut self, rect: Rect, radius: f32, color: [f32; 4]) {
+        let scissor = self.scissor();
+        self.shapes.rounded_rect(rect, radius, color, scissor);
+    }
+
+    /// Draws an anti-aliased filled circle on top of the scene, during this frame only.
+    pub fn draw_circle(&mut self, center: glam::Vec2, radius: f32, color: [f32; 4]) {
+        let size = glam::Vec2::splat(radius * 2.0);
+        self.draw_rounded_rect(Rect { position: center - size / 2.0, size, }, radius, color);
+    }
+
+    /// Writes [`StencilSettings::reference`] into the stencil inside a rounded rectangle, during this frame only. Masks
+    /// are drawn before the rest of the scene, so [`Config::stencil`]'s compare function can keep it inside or outside
+    /// of them. Does nothing without a stencil attachment.
+    pub fn mask_rounded_rect(&mut self, rect: Rect, radius: f32) {
+        self.shapes.mask_rounded_rect(rect, radius);
+    }
+
+    /// Like [`State::mask_rounded_rect`], for a circle.
+    pub fn mask_circle(&mut self, center: glam::Vec2, radius: f32) {
+        let size = glam::Vec2::splat(radius * 2.0);
+        self.shapes.mask_rounded_rect(Rect { position: center - size / 2.0, size, }, radius);
+    }
+
+    /// Draws `text` in the built-in 8x8 bitmap font on top of the scene, during this frame only, with its top-left
+    /// corner at (`x`, `y`) in physical pixels and every glyph scaled up from 8 pixels by `scale`. Characters outside
+    /// of printable ASCII show up as `?`, and newlines start a new line.
+    pub fn debug_text(&mut self, text: &str, x: f32, y: f32, scale: f32, color: [f32; 4]) {
+        self.debug_text.text(text, x, y, scale, color);
+    }
+
+    /// Whether debug lines are hidden behind scene geometry, `true` by default.
+    pub fn set_debug_depth_test(&mut self, depth_test: bool) {
+        self.debug_lines.depth_test = depth_test;
+    }
+
+    pub fn set_cull_mode(&mut self, cull_mode: Option<wgpu::Face>) {
+        if self.user_config.cull_mode == cull_mode { return };
+
+        self.user_config.cull_mode = cull_mode;
+        self.rebuild_pipeline();
+    }
+
+    pub fn set_front_face(&mut self, front_face: wgpu::FrontFace) {
+        if self.user_config.front_face == front_face { return };
+
+        self.user_config.front_face = front_face;
+        self.rebuild_pipeline();
+    }
+
+    /// Rebuilds the pipeline, the mesh's included; see [`Config::topology`].
+    pub fn set_topology(&mut self, topology: wgpu::PrimitiveTopology) {
+        if self.user_config.topology == topology { return };
+
+        self.user_config.topology = topology;
+        self.rebuild_pipeline();
+    }
+
+    /// Rebuilds the pipeline; see [`Config::alpha_to_coverage`].
+    pub fn set_alpha_to_coverage(&mut self, alpha_to_coverage: bool) {
+        if self.user_config.alpha_to_coverage == alpha_to_coverage { return };
+
+        self.user_config.alpha_to_coverage = alpha_to_coverage;
+        self.rebuild_pipeline();
+    }
+
+    /// Switches the scene pass to `samples` samples per pixel, rebuilding every pipeline drawing into it along with
+    /// the multisampled color and depth attachments. 1 turns MSAA off. Fails with
+    /// [`AndError::UnsupportedSampleCount`] without changing anything if the adapter can't multisample the scene's
+    /// formats that many times.
+    pub fn set_msaa(&mut self, samples: u32) -> Result<(), AndError> {
+        let supported = sample_counts(&self.adapter, self.scene_format, self.depth_format());
+        if !supported.contains(&samples) {
+            return Err(AndError::UnsupportedSampleCount { samples, supported, });
+        }
+        if self.user_config.msaa_samples == samples { return Ok(()) };
+
+        self.user_config.msaa_samples = samples;
+        self.rebuild_attachments();
+        self.window.request_redraw();
+        Ok(())
+    }
+
+    fn rebuild_pipeline(&mut self) {
+        self.pipeline = scene_pipeline(
+            &self.device, &self.pipeline_layout, triangle_vertex(&self.shader), "fs_main", false,
+            self.scene_format, &self.user_config,
+        );
+        if let Some((mesh, _)) = self.mesh.take() {
+            let pipelines = self.mesh_pipelines(&mesh);
+            self.mesh = Some((mesh, pipelines));
+        }
+    }
+
+    fn mesh_pipelines(&self, mesh: &Mesh) -> MeshPipelines {
+        let lit = mesh.layout().attributes.iter()
+            .any(|attribute| attribute.shader_location == 1 && attribute.format == wgpu::VertexFormat::Float32x3);
+        let (vertex_entry, fragment_entry) = if lit { ("vs_mesh_lit", "fs_mesh_lit") } else { ("vs_mesh", "fs_mesh") };
+        let pipeline = |vertex_entry, fragment_entry, buffers: &[wgpu::VertexBufferLayout], transparent| {
+            let vertex = wgpu::VertexState {
+                module: &self.shader,
+                entry_point: vertex_entry,
+                buffers,
+            };
+            scene_pipeline(
+                &self.device, &self.mesh_pipeline_layout, vertex, fragment_entry, transparent,
+                self.scene_format, &self.user_config,
+            )
+        };
+        let (instanced_vertex, instanced_fragment) = if lit {
+            ("vs_mesh_instanced_lit", "fs_mesh_instanced_lit")
+        } else {
+            ("vs_mesh_instanced", "fs_mesh_instanced")
+        };
+        MeshPipelines {
+            opaque: pipeline(vertex_entry, fragment_entry, &[mesh.layout()], false),
+            transparent: pipeline(vertex_entry, fragment_entry, &[mesh.layout()], true),
+            instanced: pipeline(
+                instanced_vertex, instanced_fragment, &[mesh.layout(), MeshDraws::instance_layout()], false,
+            ),
+        }
+    }
+
+    /// Replaces the placeholder triangle with indexed geometry seen through the camera, from vertex bytes in any
+    /// layout, e.g. straight out of a mesher. The scene shader reads a `Float32x3` position at shader location 0,
+    /// which `layout` has to have, and locations 12 to 15, which hold the transforms of
+    /// [`State::set_culled_instances`], are reserved. The stride, attribute bounds and indices are checked against
+    /// `vertices` and the device limits, failing with [`AndError::InvalidMesh`].
+    pub fn set_mesh_raw(
+        &mut self, vertices: &[u8], layout: wgpu::VertexBufferLayout<'static>, indices: &[u32],
+    ) -> Result<(), AndError> {
+        let mesh = Mesh::new(&self.device, vertices, &layout, indices)?;
+        let pipelines = self.mesh_pipelines(&mesh);
+        self.mesh = Some((mesh, pipelines));
+        Ok(())
+    }
+
+    /// Like [`State::set_mesh_raw`] with [`Vertex::layout`], split into `submeshes` for pipelines that draw ranges
+    /// of the indices on their own. Failing with [`AndError::InvalidMesh`] if any lies outside of them.
+    pub fn set_mesh(&mut self, vertices: &[Vertex], indices: &[u32], submeshes: Vec<Submesh>) -> Result<(), AndError> {
+        let mesh = Mesh::new(&self.device, bytemuck::cast_slice(vertices), &Vertex::layout(), indices)?
+            .with_submeshes(submeshes)?;
+        let pipelines = self.mesh_pipelines(&mesh);
+        self.mesh = Some((mesh, pipelines));
+        Ok(())
+    }
+
+    /// Loads an OBJ file through [`load_obj`] and makes it the mesh, with a submesh per object in the file. Returns
+    /// the model, e.g. to load the textures its materials refer to.
+    #[cfg(feature = "obj")]
+    pub fn load_obj(&mut self, path: impl AsRef<Path>) -> Result<ObjModel, AndError> {
+        let model = load_obj(path)?;
+        self.set_mesh(&model.vertices, &model.indices, model.submeshes.clone())?;
+        Ok(model)
+    }
+
+    /// Submeshes of the mesh set through [`State::set_mesh`], empty for none or a raw mesh.
+    pub fn submeshes(&self) -> &[Submesh] {
+        self.mesh.as_ref().map_or(&[], |(mesh, _)| mesh.submeshes())
+    }
+
+    /// Replaces this frame's mesh draws with a white instance of the mesh per item whose bounds, given in the mesh's
+    /// space, are in any view's [`Frustum`], and returns how many were kept. Nothing is drawn if none are. This is
+    /// checked on the CPU, so only the kept transforms are uploaded, and they're drawn in a single instanced call;
+    /// for other colors or transparency, cull [`MeshDraw`]s against [`State::frustums`] before passing them to
+    /// [`State::draw_mesh`] instead.
+    pub fn set_culled_instances(&mut self, items: &[(glam::Mat4, Aabb)]) -> usize {
+        let frustums = self.frustums();
+        self.mesh_draws.replace_instanced(items.iter()
+            .filter(|&&(transform, aabb)| frustums.iter().any(|frustum| frustum.intersects(transform, aabb)))
+            .map(|&(transform, _)| transform));
+        self.mesh_draws.instance_count()
+    }
+
+    /// One frustum per view, the main camera's first, as they'll be at the current surface size.
+    pub fn frustums(&self) -> Vec<Frustum> {
+        let (width, height) = self.content_size();
+        self.view_projs(width, height).into_iter().map(Frustum::from_view_proj).collect()
+    }
+
+    /// Queues a draw of the mesh set through [`State::set_mesh_raw`] for this frame. Without any, the mesh is drawn
+    /// once, untransformed and white. Transparent draws go after opaque ones, sorted as per
+    /// [`Config::sort_transparency`].
+    pub fn draw_mesh(&mut self, draw: MeshDraw) {
+        self.mesh_draws.push(draw);
+    }
+
+    /// Goes back to drawing the placeholder triangle.
+    pub fn clear_mesh(&mut self) {
+        self.mesh = None;
+    }
+
+    /// Lights meshes with normals from `direction`, scaling diffuse shading by `color` on top of `ambient`; see
+    /// [`Config::light`].
+    pub fn set_light(&mut self, direction: glam::Vec3, color: [f32; 3], ambient: [f32; 3]) {
+        self.user_config.light = Some(DirectionalLight { direction, color, ambient, });
+        self.light.write(&self.queue, self.user_config.light);
+    }
+
+    /// Goes back to drawing meshes in their flat colors.
+    pub fn clear_light(&mut self) {
+        self.user_config.light = None;
+        self.light.write(&self.queue, None);
+    }
+
+    /// The graphics API the adapter runs on, e.g. to work around something broken on one backend.
+    pub fn backend(&self) -> wgpu::Backend {
+        self.adapter_info.backend
+    }
+
+    /// Name, vendor, device type and backend of the adapter, queried once at startup.
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// Shows or hides the graph of [`State::frame_time_history`] in the top-left corner, which F3 also toggles.
+    /// Below it are the average frame time and FPS, the GPU time of a recent frame, and the adapter's name and
+    /// backend. The GPU time needs [`wgpu::Features::TIMESTAMP_QUERY`], and reads "n/a" without it. Adding the
+    /// overlay to a frame isn't counted in that frame's time.
+    #[cfg(feature = "overlay")]
+    pub fn set_overlay(&mut self, overlay: bool) {
+        self.overlay = overlay;
+    }
+
+    #[cfg(feature = "overlay")]
+    pub fn overlay(&self) -> bool {
+        self.overlay
+    }
+
+    /// A multi-line summary of the adapter, driver, surface and device limits in use, for pasting into bug reports.
+    pub fn diagnostics(&self) -> String {
+        let info = &self.adapter_info;
+        let limits = self.device.limits();
+        let driver = match (info.driver.is_empty(), info.driver_info.is_empty()) {
+            (true, _) => "unknown".to_string(),
+            (false, true) => info.driver.clone(),
+            (false, false) => format!("{} ({})", info.driver, info.driver_info),
+        };
+
+        [
+            format!("Adapter: {} ({:?}, {:?})", info.name, info.device_type, info.backend),
+            format!("PCI IDs: {:04x}:{:04x}", info.vendor, info.device),
+            format!("Driver: {driver}"),
+            format!(
+                "Surface: {:?} at {}x{}, {:?}",
+                self.config.format, self.config.width, self.config.height, self.config.alpha_mode,
+            ),
+            format!("Scene: {:?} at {}x{}", self.scene_format, self.target.width, self.target.height),
+            format!("Present mode: {:?}", self.config.present_mode),
+            format!("MSAA samples: {}", self.user_config.msaa_samples),
+            format!(
+                "Limits: {} max 2D texture size, {} bind groups, {} B uniform / {} B storage bindings, \
+                 {} vertex buffers",
+                limits.max_texture_dimension_2d, limits.max_bind_groups, limits.max_uniform_buffer_binding_size,
+                limits.max_storage_buffer_binding_size, limits.max_vertex_buffers,
+            ),
+        ].join("\n")
+    }
+
+    /// Feature flags of `format` on the adapter, such as which MSAA sample counts it supports.
+    pub fn format_sample_flags(&self, format: wgpu::TextureFormat) -> wgpu::TextureFormatFeatureFlags {
+        self.adapter.get_texture_format_features(format).flags
+    }
+
+    /// MSAA sample counts `format` supports on the adapter, in ascending order and always including 1, e.g. to only
+    /// offer those in a settings menu. [`State::set_msaa`] takes the ones both the scene's color and depth formats
+    /// support.
+    pub fn supported_sample_counts(&self, format: wgpu::TextureFormat) -> Vec<u32> {
+        format_sample_counts(&self.adapter, format)
+    }
+
+    pub fn supports_storage_binding(&self, format: wgpu::TextureFormat) -> bool {
+        self.adapter.get_texture_format_features(format).allowed_usages.contains(wgpu::TextureUsages::STORAGE_BINDING)
+    }
+
+    /// See [`Config::gamma`]; clamped to `0.5..=2.5`.
+    pub fn set_gamma(&mut self, gamma: f32) {
+        self.user_config.gamma = gamma;
+        self.update_adjustment();
+    }
+
+    /// See [`Config::brightness`]; clamped to `0.5..=2.0`.
+    pub fn set_brightness(&mut self, brightness: f32) {
+        self.user_config.brightness = brightness;
+        self.update_adjustment();
+    }
+
+    /// Clamps [`Config::gamma`] and [`Config::brightness`] to their ranges and hands them to the blit.
+    fn update_adjustment(&mut self) {
+        self.user_config.gamma = self.user_config.gamma.clamp(0.5, 2.5);
+        self.user_config.brightness = self.user_config.brightness.clamp(0.5, 2.0);
+        self.blit.set_adjustment(&self.queue, self.user_config.gamma, self.user_config.brightness);
+        self.window.request_redraw();
+    }
+
+    /// Switches the tonemapping operator, changing the scene format and rebuilding everything that depends on it if
+    /// it goes from or to [`Tonemap::None`].
+    pub fn set_tonemap(&mut self, tonemap: Tonemap) {
+        self.user_config.tonemap = tonemap;
+        self.update_scene_format();
+    }
+
+    /// Switches between rendering the scene into a linear float target or straight into the surface format,
+    /// rebuilding everything that depends on it.
+    pub fn set_hdr(&mut self, hdr: bool) {
+        self.user_config.hdr = hdr;
+        self.update_scene_format();
+    }
+
+    /// Returns whether the format changed, in which case everything drawing into it was rebuilt.
+    fn update_scene_format(&mut self) -> bool {
+        let format = scene_format(&self.user_config, self.config.format, &self.adapter);
+        let changed = format != self.scene_format;
+        if changed {
+            self.scene_format = format;
+            self.rebuild_attachments();
+            if self.recorder.take().is_some() {
+                log::warn!("Scene format changed, stopping frame recording");
+            }
+        }
+
+        self.blit.set_tonemap(&self.queue, self.user_config.tonemap, encode_srgb(format, self.config.format));
+        changed
+    }
+
+    /// Builds a render pipeline for [`Config::on_render`] to use under `name`, replacing any by the same name. The
+    /// builder is invoked again whenever the scene's formats or sample count change or the device is recreated, so
+    /// it shouldn't capture anything device-specific.
+    pub fn register_pipeline(
+        &mut self, name: impl Into<String>, builder: impl Fn(&PipelineTarget) -> wgpu::RenderPipeline + 'static,
+    ) {
+        let target = PipelineTarget {
+            device: &self.device,
+            color_format: self.scene_format,
+            depth_format: self.depth_format(),
+            sample_count: self.user_config.msaa_samples,
+            uniforms: &self.uniforms,
+        };
+        self.pipelines.insert(name.into(), Box::new(builder), &target);
+    }
+
+    /// Returns whether there was a pipeline by that name.
+    pub fn unregister_pipeline(&mut self, name: &str) -> bool {
+        self.pipelines.remove(name)
+    }
+
+    pub fn pipelines(&self) -> &Pipelines {
+        &self.pipelines
+    }
+
+    /// Adds a render pass under `name`, drawn into the scene's color and depth attachments after the scene pass and
+    /// every pass registered before it, replacing any by the same name in its place. With `load` unset, it draws on
+    /// top of whatever the passes before it left; set it to clear the color attachment itself. Depth and stencil are
+    /// always loaded. Passes are kept across device loss, like registered pipelines.
+    pub fn register_pass(
+        &mut self, name: impl Into<String>, load: Option<wgpu::LoadOp<wgpu::Color>>,
+        draw: impl for<'a> FnMut(&mut wgpu::RenderPass<'a>, &RenderContext<'a>) + 'static,
+    ) {
+        self.passes.insert(name.into(), load, Box::new(draw));
+    }
+
+    /// Returns whether there was a pass by that name.
+    pub fn unregister_pass(&mut self, name: &str) -> bool {
+        self.passes.remove(name)
+    }
+
+    pub fn passes(&self) -> &Passes {
+        &self.passes
+    }
+
+    /// Writes `value` to a uniform buffer at `group` and `binding`, creating the buffer and the group's bind group
+    /// the first time, or whenever `T` changes size. Registered pipelines are rebuilt whenever that changes a
+    /// group's layout, so builders should take it from [`PipelineTarget::uniforms`]; bind the groups in
+    /// [`Config::on_render`] through [`RenderContext::uniforms`].
+    ///
+    /// `T` is copied as is, so its layout has to match WGSL's: `vec3`s and `vec4`s are aligned to 16 bytes, and
+    /// structs to their most aligned member, which `#[repr(C)]` doesn't do on its own. Add explicit padding fields
+    /// where needed. The buffer is padded to a multiple of [`Uniforms::ALIGNMENT`], so trailing padding can be left
+    /// out. Fails with [`AndError::InvalidUniform`] if the group or size is over the device limits.
+    pub fn set_uniform<T: bytemuck::Pod>(&mut self, group: u32, binding: u32, value: &T) -> Result<(), AndError> {
+        if self.uniforms.set(&self.device, &self.queue, group, binding, bytemuck::bytes_of(value))? {
+            self.rebuild_registered_pipelines();
+        }
+        Ok(())
+    }
+
+    pub fn uniforms(&self) -> &Uniforms {
+        &self.uniforms
+    }
+
+    fn rebuild_registered_pipelines(&mut self) {
+        let target = PipelineTarget {
+            device: &self.device,
+            color_format: self.scene_format,
+            depth_format: self.depth_format(),
+            sample_count: self.user_config.msaa_samples,
+            uniforms: &self.uniforms,
+        };
+        self.pipelines.rebuild(&target);
+    }
+
+    /// Rebuilds everything that depends on the scene's color or depth format, or its sample count. Turns MSAA off
+    /// first if the formats can't be multisampled.
+    fn rebuild_attachments(&mut self) {
+        fallback_msaa(&mut self.user_config, &self.adapter, self.scene_format);
+        let (format, depth_format, samples) = (self.scene_format, self.depth_format(), self.user_config.msaa_samples);
+        self.rebuild_pipeline();
+        self.rebuild_registered_pipelines();
+        self.debug_lines = DebugLines::new(&self.device, format, depth_format, samples, self.user_config.reverse_z);
+        self.rebuild_grid();
+        self.particles = ParticleBatch::new(&self.device, format, depth_format, samples, self.user_config.reverse_z);
+        self.sprites.set_format(&self.device, format, depth_format, samples, &self.texture_layout);
+        self.shapes = Shapes::new(&self.device, format, depth_format, samples);
+        self.debug_text = DebugText::new(&self.device, &self.queue, format, depth_format, samples);
+        let (width, height) = self.target_size();
+        self.target = FrameTarget::new(&self.device, &self.blit, format, depth_format, samples, width, height);
+        self.rebuild_bloom();
+        self.accumulation = (self.user_config.accumulation_frames > 0).then(|| Accumulation::new(
+            &self.device, &self.blit, format, self.user_config.accumulation_frames, width, height,
+        ));
+    }
+
+    fn depth_format(&self) -> wgpu::TextureFormat {
+        target::depth_format(self.user_config.stencil.is_some())
+    }
+
+    /// Changes the stencil ops, reference or clear value, rebuilding the scene pipeline. Adding or removing the
+    /// stencil attachment altogether changes the depth format, which rebuilds everything drawing into it.
+    pub fn set_stencil(&mut self, stencil: Option<StencilSettings>) {
+        if self.user_config.stencil == stencil { return };
+
+        let previous = std::mem::replace(&mut self.user_config.stencil, stencil);
+        match (previous, stencil) {
+            // The reference and clear value are set per pass.
+            (Some(previous), Some(stencil)) if previous.state() == stencil.state() => {},
+            (Some(_), Some(_)) => self.rebuild_pipeline(),
+            _ => self.rebuild_attachments(),
+        }
+    }
+
+    /// Turns bloom on, off, or changes its settings in place.
+    pub fn set_bloom(&mut self, bloom: Option<BloomSettings>) {
+        self.user_config.bloom = bloom;
+        match (self.bloom.as_ref(), bloom) {
+            (Some(pass), Some(settings)) => pass.set_settings(&self.queue, settings),
+            _ => self.rebuild_bloom(),
+        }
+    }
+
+    fn rebuild_bloom(&mut self) {
+        let (width, height) = (self.target.width, self.target.height);
+        self.bloom = self.user_config.bloom
+            .map(|settings| Bloom::new(&self.device, self.scene_format, settings, &self.target.view, width, height));
+    }
+
+    /// The present mode actually in use, as resolved from [`Config::present_mode_policy`] or [`Config::present_mode`].
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.config.present_mode
+    }
+
+    /// Refresh rate of the monitor the window is on, if the platform reports it, e.g. to step animations in whole
+    /// refreshes. Queried again whenever the window moves or changes scale factor, which is when it may have ended up
+    /// on another monitor.
+    pub fn refresh_rate_hz(&self) -> Option<f32> {
+        self.refresh_rate
+    }
+
+    fn update_refresh_rate(&mut self) {
+        let refresh_rate = refresh_rate(&self.window);
+        if refresh_rate != self.refresh_rate {
+            log::debug!("Monitor refresh rate changed to {refresh_rate:?} Hz");
+            self.refresh_rate = refresh_rate;
+        }
+    }
+
+    /// Time between vertical blanks of the monitor the window is on, if the platform reports its refresh rate.
+    fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh_rate.map(|hz| Duration::from_secs_f64(1.0 / hz as f64))
+    }
+
+    /// Best guess at when a frame rendered now will show up: the first vertical blank after now, counting refresh
+    /// intervals from when the last frame was presented. wgpu doesn't expose presentation timing, so this drifts by
+    /// however late that present went out, and is only meaningful with a vsynced present mode. `None` before the
+    /// first frame, or if the monitor's refresh rate is unknown.
+    pub fn predicted_present_time(&self) -> Option<Instant> {
+        let last_present = self.last_present?;
+        let interval = self.refresh_interval()?;
+
+        let elapsed = last_present.elapsed();
+        let intervals = (elapsed.as_secs_f64() / interval.as_secs_f64()).floor() as u32 + 1;
+        Some(last_present + interval * intervals)
+    }
+
+    /// Switches the present mode, e.g. from a vsync toggle in a settings menu, overriding
+    /// [`Config::present_mode_policy`]. Unsupported modes fall back to `Fifo`. Only the surface is reconfigured, so
+    /// this is cheap enough to call from UI code; the device, pipelines and render targets are left as they are.
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) {
+        self.user_config.present_mode = mode;
+        self.user_config.present_mode_policy = PresentModePolicy::Auto;
+
+        let resolved = present_mode(&self.user_config, &self.surface.get_supported_present_modes(&self.adapter));
+        if resolved == self.config.present_mode { return };
+
+        log::info!("Switching present mode to {resolved:?}");
+        self.config.present_mode = resolved;
+        self.configure_surface();
+        self.window.request_redraw();
+    }
+
+    /// Size of the surface, in physical pixels.
+    pub fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.config.width, self.config.height)
+    }
+
+    pub fn surface_format(&self) -> wgpu::TextureFormat {
+        self.config.format
+    }
+
+    /// Formats the surface can be configured with on this adapter, the preferred one first.
+    pub fn supported_surface_formats(&self) -> Vec<wgpu::TextureFormat> {
+        self.surface.get_supported_formats(&self.adapter)
+    }
+
+    /// Reconfigures the surface with `format`, e.g. to compare an sRGB surface against a linear one, and keeps it
+    /// as [`Config::surface_format`] for when the device is recreated. A scene rendered straight in the surface's
+    /// format follows it, rebuilding everything drawing into it.
+    pub fn set_surface_format(&mut self, format: wgpu::TextureFormat) -> Result<(), AndError> {
+        let supported = self.supported_surface_formats();
+        if !supported.contains(&format) {
+            return Err(AndError::UnsupportedFormat { format, supported, });
+        }
+
+        self.user_config.surface_format = Some(format);
+        if self.switch_surface_format(format) {
+            self.update_scene_format();
+            self.window.request_redraw();
+        }
+        Ok(())
+    }
+
+    /// Leaves updating the scene format to the caller. Returns whether the format changed.
+    fn switch_surface_format(&mut self, format: wgpu::TextureFormat) -> bool {
+        if format == self.config.format { return false };
+
+        log::info!("Switching surface format to {format:?}");
+        self.config.format = format;
+        self.configure_surface();
+        self.blit.set_format(&self.device, format);
+        true
+    }
+
+    /// Format the scene itself is rendered in; the surface's, unless it goes through a linear target first.
+    pub fn scene_format(&self) -> wgpu::TextureFormat {
+        self.scene_format
+    }
+
+    pub fn depth_clear(&self) -> f32 {
+        self.depth_clear
+    }
+
+    /// Sets the value the depth attachment is cleared to each frame, e.g. `0.0` for reverse-Z.
+    pub fn set_depth_clear(&mut self, depth_clear: f32) -> Result<(), AndError> {
+        if !(0.0..=1.0).contains(&depth_clear) {
+            return Err(AndError::InvalidDepthClear(depth_clear));
+        }
+
+        self.depth_clear = depth_clear;
+        Ok(())
+    }
+
+    /// Shows or hides the title bar and borders. Does nothing on platforms without window decorations, such as
+    /// Android and the web.
+    pub fn set_decorations(&mut self, decorations: bool) {
+        self.user_config.decorations = decorations;
+        self.window.set_decorations(decorations);
+    }
+
+    /// Keeps the window above all others or not. Does nothing on platforms without window stacking, such as Android
+    /// and the web.
+    pub fn set_always_on_top(&mut self, always_on_top: bool) {
+        self.user_config.always_on_top = always_on_top;
+        self.window.set_always_on_top(always_on_top);
+    }
+
+    /// A snapshot of every setting in use, e.g. to go back to when a settings menu is cancelled with
+    /// [`State::apply_config`]. Callbacks can't be copied, so they're all `None` in it.
+    pub fn config(&self) -> Config {
+        self.user_config.without_callbacks()
+    }
+
+    /// Switches to `config`, rebuilding only what the changed settings affect: pipelines, the scene's attachments,
+    /// passes such as bloom, or just the surface. Callbacks that are `None` in it, as in any [`State::config`]
+    /// snapshot, keep the current ones. Settings that only apply when the window and device are created, such as
+    /// [`Config::limits_preset`], [`Config::fullscreen`] and the initial window size and position, are kept for the
+    /// next time that happens without taking effect now. An unsupported [`Config::surface_format`] or
+    /// [`Config::address_modes`] keeps the previous setting.
+    pub fn apply_config(&mut self, config: Config) {
+        let mut previous = std::mem::replace(&mut self.user_config, config);
+        let current = &mut self.user_config;
+        if current.on_resize.is_none() { current.on_resize = previous.on_resize.take() };
+        if current.on_frame.is_none() { current.on_frame = previous.on_frame.take() };
+        if current.on_key.is_none() { current.on_key = previous.on_key.take() };
+        if current.on_user_event.is_none() { current.on_user_event = previous.on_user_event.take() };
+        if current.on_pre_render.is_none() { current.on_pre_render = previous.on_pre_render.take() };
+        if current.on_post_submit.is_none() { current.on_post_submit = previous.on_post_submit.take() };
+        if current.on_render.is_none() { current.on_render = previous.on_render.take() };
+        if current.on_render_frame.is_none() { current.on_render_frame = previous.on_render_frame.take() };
+        if current.on_device_recovered.is_none() {
+            current.on_device_recovered = previous.on_device_recovered.take();
+        }
+        if let Err(e) = current.address_modes.check(self.device.features()) {
+            log::warn!("{e}, keeping {:?}", previous.address_modes);
+            current.address_modes = previous.address_modes;
+        }
+
+        let current = &self.user_config;
+        let stencil_attachment = previous.stencil.is_some() != current.stencil.is_some();
+        let stencil_state = |config: &Config| config.stencil.map(|stencil| stencil.state());
+        let stencil_state = stencil_state(&previous) != stencil_state(current);
+        let pipeline = stencil_state
+            || (previous.cull_mode, previous.front_face, previous.topology, previous.alpha_to_coverage)
+                != (current.cull_mode, current.front_face, current.topology, current.alpha_to_coverage);
+        let attachments = stencil_attachment
+            || previous.msaa_samples != current.msaa_samples
+            || previous.reverse_z != current.reverse_z
+            || previous.accumulation_frames != current.accumulation_frames;
+        let target = previous.aspect != current.aspect;
+        if previous.control_flow != current.control_flow {
+            self.next_frame = None;
+        }
+        if previous.light != current.light {
+            self.light.write(&self.queue, current.light);
+        }
+        let presenting = (previous.present_mode, previous.present_mode_policy)
+            != (current.present_mode, current.present_mode_policy);
+        let adjustment = (previous.gamma, previous.brightness) != (current.gamma, current.brightness);
+        let frames_in_flight = previous.frames_in_flight != current.frames_in_flight;
+
+        if previous.reverse_z != current.reverse_z {
+            self.depth_clear = if current.reverse_z { 0.0 } else { 1.0 };
+        }
+        if previous.address_modes != current.address_modes {
+            self.texture_bind_groups.clear();
+        }
+        if previous.frame_time_history != current.frame_time_history {
+            self.frame_times = FrameTimes::new(current.frame_time_history);
+        }
+        if previous.adaptive_resolution != current.adaptive_resolution {
+            self.adaptive = current.adaptive_resolution.map(AdaptiveResolution::new);
+        }
+        if previous.decorations != current.decorations {
+            self.window.set_decorations(current.decorations);
+        }
+        if previous.always_on_top != current.always_on_top {
+            self.window.set_always_on_top(current.always_on_top);
+        }
+        if previous.defer_present && !current.defer_present {
+            self.flush_pending_frame();
+        }
+        if previous.surface_format != self.user_config.surface_format {
+            let supported = self.supported_surface_formats();
+            match self.user_config.surface_format {
+                Some(format) if !supported.contains(&format) => {
+                    log::warn!("{format:?} isn't supported by the surface, keeping {:?}", self.config.format);
+                    self.user_config.surface_format = previous.surface_format;
+                },
+                format => {
+                    self.switch_surface_format(format.unwrap_or(supported[0]));
+                },
+            }
+        }
+
+        // A new scene format or depth attachment rebuilds everything drawing into them, which covers the rest.
+        let (bloom, grid) = (self.user_config.bloom, self.user_config.grid);
+        if !self.update_scene_format() {
+            if attachments {
+                self.rebuild_attachments();
+            } else {
+                if pipeline {
+                    self.rebuild_pipeline();
+                }
+                if target {
+                    self.resize_target();
+                }
+                if previous.bloom != bloom {
+                    match (self.bloom.as_ref(), bloom) {
+                        (Some(pass), Some(settings)) => pass.set_settings(&self.queue, settings),
+                        _ => self.rebuild_bloom(),
+                    }
+                }
+                if previous.grid != grid {
+                    self.set_grid(grid);
+                }
+            }
+        }
+
+        if adjustment {
+            self.update_adjustment();
+        }
+        if frames_in_flight {
+            self.uniforms.recreate(&self.device, self.user_config.frames_in_flight);
+            self.rebuild_registered_pipelines();
+        }
+        if presenting {
+            let resolved = present_mode(&self.user_config, &self.surface.get_supported_present_modes(&self.adapter));
+            if resolved != self.config.present_mode {
+                log::info!("Switching present mode to {resolved:?}");
+                self.config.present_mode = resolved;
+                self.configure_surface();
+            }
+        }
+
+        self.window.request_redraw();
+    }
+
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+
+    pub fn modifiers(&self) -> ModifiersState {
+        self.input.modifiers
+    }
+
+    /// A handle other threads can use to wake the event loop with a [`UserEvent`].
+    pub fn event_loop_proxy(&self) -> EventLoopProxy<UserEvent> {
+        self.proxy.clone()
+    }
+
+    /// Feeds an event from the host's event loop to a state built through [`State::from_parts`]: its window's input,
+    /// resizes and redraws, and [`UserEvent`]s. Events of other windows are ignored, and everything else, such as
+    /// closing the window, is left to the host. A frame that fails in a way only recreating the device would fix is
+    /// logged, and nothing is rendered from then on.
+    pub fn handle_event(&mut self, event: Event<'_, UserEvent>) {
+        match event {
+            Event::NewEvents(_) => self.new_events(),
+            Event::WindowEvent { window_id, event, } if window_id == self.window.id() => self.window_event(event),
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta, }, .. } => self.mouse_motion(delta),
+            Event::RedrawRequested(window_id) if window_id == self.window.id() && self.failure.is_none() => {
+                if let Err(failure) = self.present_frame() {
+                    log::error!("Stopping rendering after {failure:?}, the host's device can't be recovered here");
+                    self.failure = Some(failure);
+                }
+            },
+            Event::UserEvent(event) => self.user_event(event),
+            _ => {},
+        }
+    }
+
+    /// Requests the next frame if [`Config::control_flow`] calls for one, returning how the loop should wait for it.
+    /// [`run`] calls this once all events of an iteration are handled; hosts driving a state built through
+    /// [`State::from_parts`] can do the same. Nothing is requested while the window is occluded, and while recovering
+    /// from device loss, this waits until the next attempt at it is due.
+    pub fn next_control_flow(&mut self) -> ControlFlow {
+        if let Some(recovery) = self.recovery { return ControlFlow::WaitUntil(recovery.at) };
+        if self.awaiting_size || self.occluded { return ControlFlow::Wait };
+
+        match self.user_config.control_flow {
+            ControlFlowPolicy::Wait => ControlFlow::Wait,
+            ControlFlowPolicy::Poll => {
+                self.window.request_redraw();
+                ControlFlow::Poll
+            },
+            ControlFlowPolicy::WaitUntil(fps) => {
+                let interval = Duration::from_secs_f32(1.0 / fps.max(1.0));
+                let now = Instant::now();
+                let deadline = *self.next_frame.get_or_insert(now);
+                if now >= deadline {
+                    self.window.request_redraw();
+                    // Keeps a steady cadence, but starts over after falling behind rather than catching up in a burst.
+                    let next = deadline + interval;
+                    self.next_frame = Some(if next > now { next } else { now + interval });
+                }
+
+                ControlFlow::WaitUntil(self.next_frame.expect("Set above"))
+            },
+        }
+    }
+
+    pub fn set_control_flow(&mut self, control_flow: ControlFlowPolicy) {
+        self.user_config.control_flow = control_flow;
+        self.next_frame = None;
+    }
+
+    /// Time spent waiting for events doesn't count against the budget.
+    fn new_events(&mut self) {
+        self.drained_events = 0;
+        self.drain_start = Instant::now();
+    }
+
+    fn window_event(&mut self, event: WindowEvent) {
+        match event {
+            WindowEvent::Resized(PhysicalSize { width, height, }) => self.resize(width, height),
+            WindowEvent::ModifiersChanged(modifiers) => self.input.modifiers = modifiers,
+            WindowEvent::KeyboardInput { input, .. } => self.key(input),
+            WindowEvent::Focused(focused) => self.focused(focused),
+            WindowEvent::Occluded(occluded) => self.occluded(occluded),
+            WindowEvent::CursorMoved { position, .. } => self.cursor_moved(position),
+            WindowEvent::CursorLeft { .. } => self.input.cursor_position = None,
+            WindowEvent::Moved(_) | WindowEvent::ScaleFactorChanged { .. } => self.update_refresh_rate(),
+            _ => {},
+        }
+        self.count_event();
+    }
+
+    fn mouse_motion(&mut self, (x, y): (f64, f64)) {
+        self.input.mouse_delta += glam::Vec2::new(x as f32, y as f32);
+        self.count_event();
+    }
+
+    fn user_event(&mut self, event: UserEvent) {
+        match event {
+            UserEvent::Redraw => self.window.request_redraw(),
+            UserEvent::Custom(payload) => if let Some(mut on_user_event) = self.user_config.on_user_event.take() {
+                on_user_event(self, payload);
+                self.user_config.on_user_event = Some(on_user_event);
+            },
+        }
+        self.count_event();
+    }
+
+    fn key(&mut self, input: KeyboardInput) {
+        let fresh = self.input.key(&input);
+        #[cfg(feature = "overlay")]
+        if fresh && input.state == winit::event::ElementState::Pressed
+            && input.virtual_keycode == Some(winit::event::VirtualKeyCode::F3)
+        {
+            self.set_overlay(!self.overlay);
+        }
+        if !fresh && !self.user_config.key_repeats { return };
+        if let Some(mut on_key) = self.user_config.on_key.take() {
+            on_key(self, input);
+            self.user_config.on_key = Some(on_key);
+        }
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    /// The camera's view-projection for the current content size, honoring [`Config::reverse_z`].
+    pub fn view_proj(&self) -> glam::Mat4 {
+        let (width, height) = self.content_size();
+        self.view_proj_for(width, height)
+    }
+
+    /// The camera's view-projection for a `width` by `height` target, matching the aspect ratio of its viewport.
+    pub fn view_proj_for(&self, width: u32, height: u32) -> glam::Mat4 {
+        self.camera.view_proj(self.viewport.aspect(width, height), self.user_config.reverse_z)
+    }
+
+    fn poll_textures(&mut self) {
+        for handle in self.loader.poll() {
+            self.texture_bind_groups.invalidate(&handle);
+        }
+    }
+
+    /// Starts loading an image file in the background. The texture becomes available through [`State::texture`]
+    /// once it's done, at the start of some later frame. With the `ktx2` feature, `.ktx2` files are loaded as
+    /// block-compressed textures; if loading fails, [`State::texture_failure`] says why instead. Only fails right
+    /// away if the loader thread is gone.
+    pub fn load_texture_async(&mut self, path: impl AsRef<Path>) -> Result<TextureHandle, AndError> {
+        self.loader.request(path.as_ref().to_path_buf(), false)
+    }
+
+    /// Like [`State::load_texture_async`], but multiplies the color by alpha before uploading, so sprites drawn
+    /// with [`BlendMode::Premultiplied`] filter without dark fringes around their translucent edges. The default
+    /// [`BlendMode::Alpha`] would darken them instead. KTX2 files are loaded as they are.
+    pub fn load_texture_premultiplied(&mut self, path: impl AsRef<Path>) -> Result<TextureHandle, AndError> {
+        self.loader.request(path.as_ref().to_path_buf(), true)
+    }
+
+    /// Runs `decode` on the texture loader thread and uploads the image it yields from there, so neither decoding
+    /// nor uploading large textures holds up a frame, e.g. for streaming in formats the loader can't open itself.
+    /// Becomes available through [`State::texture`] like [`State::load_texture_async`]. Jobs still queued or
+    /// running when the device is lost are dropped along with the loaded textures. A panic in `decode` fails only
+    /// this texture, reported as [`AndError::LoaderPanic`].
+    pub fn stream_texture(
+        &mut self, decode: impl FnOnce() -> Result<DecodedImage, AndError> + Send + 'static,
+    ) -> Result<TextureHandle, AndError> {
+        self.loader.stream(decode)
+    }
+
+    /// Creates a texture from `width` by `height` tightly packed sRGB RGBA pixels right away, e.g. for generated
+    /// content.
+    pub fn create_texture(&mut self, width: u32, height: u32, rgba: &[u8]) -> TextureHandle {
+        self.loader.insert(loader::create(&self.device, &self.queue, Some("Created texture"), width, height, rgba))
+    }
+
+    pub fn texture(&self, handle: TextureHandle) -> Option<&Texture> {
+        self.loader.get(handle)
+    }
+
+    /// Why the texture behind `handle` couldn't be loaded, once that's known. A handle with neither a texture nor a
+    /// failure is still loading.
+    pub fn texture_failure(&self, handle: TextureHandle) -> Option<&AndError> {
+        self.loader.failure(handle)
+    }
+
+    /// Frees a loaded texture along with its cached bind group.
+    pub fn unload_texture(&mut self, handle: TextureHandle) {
+        self.loader.remove(handle);
+        self.texture_address_modes.remove(&handle);
+        self.texture_bind_groups.invalidate(&handle);
+    }
+
+    /// Changes the address modes of every texture that doesn't override them. Fails with
+    /// [`AndError::UnsupportedAddressMode`], keeping the current ones, if the device can't clamp to the border.
+    pub fn set_address_modes(&mut self, modes: AddressModes) -> Result<(), AndError> {
+        if self.user_config.address_modes == modes { return Ok(()) };
+
+        self.user_config.address_modes = modes.check(self.device.features())?;
+        self.texture_bind_groups.clear();
+        Ok(())
+    }
+
+    /// Overrides [`Config::address_modes`] for one texture, e.g. `Repeat` to tile it across a large quad. `None`
+    /// goes back to the default. Fails like [`State::set_address_modes`].
+    pub fn set_texture_address_modes(
+        &mut self, handle: TextureHandle, modes: Option<AddressModes>,
+    ) -> Result<(), AndError> {
+        let previous = match modes {
+            Some(modes) => self.texture_address_modes.insert(handle, modes.check(self.device.features())?),
+            None => self.texture_address_modes.remove(&handle),
+        };
+        if previous != modes {
+            self.texture_bind_groups.invalidate(&handle);
+        }
+        Ok(())
+    }
+
+    /// Layout of the bind groups from [`State::texture_bind_group`], for building pipelines that sample textures.
+    pub fn texture_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.texture_layout
+    }
+
+    /// A bind group of the texture and a linear sampler with its address modes, if it's done loading. Created once
+    /// and reused on later frames, until the texture is unloaded or its address modes change.
+    pub fn texture_bind_group(&mut self, handle: TextureHandle) -> Option<&wgpu::BindGroup> {
+        let texture = self.loader.get(handle)?;
+        let modes = self.texture_address_modes.get(&handle).copied().unwrap_or(self.user_config.address_modes);
+        let sampler = self.samplers.get(&self.device, modes);
+        let (device, layout) = (&self.device, &self.texture_layout);
+
+        Some(self.texture_bind_groups.get_or_create(handle, || device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Texture bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })))
+    }
+
+    /// Reads back the color of the last rendered frame under `(x, y)` in the window, in physical pixels like
+    /// [`WindowEvent::CursorMoved`], as RGBA regardless of the scene format's channel order. Only 8-bit RGBA and
+    /// BGRA scenes can be read, failing with [`AndError::UnreadableFormat`] for e.g. float ones. Positions in the
+    /// bars around a letterboxed scene are out of bounds, and the render scale is accounted for. Blocks until the GPU
+    /// is done with the copy.
+    pub fn read_pixel(&self, x: u32, y: u32) -> Result<[u8; 4], AndError> {
+        let swizzle = capture::swizzle(self.scene_format)?;
+        let content = self.content_rect();
+        let position = glam::Vec2::new(x as f32, y as f32) - content.position;
+        if position.x < 0.0 || position.y < 0.0 || position.x >= content.size.x || position.y >= content.size.y {
+            let (width, height) = (self.config.width, self.config.height);
+            return Err(AndError::OutOfBounds { x, y, width, height, });
+        }
+
+        // The scene may be rendered at a different resolution than the content rect.
+        let scale = glam::Vec2::new(self.target.width as f32, self.target.height as f32) / content.size;
+        let target = (position * scale).floor();
+        let (x, y) = ((target.x as u32).min(self.target.width - 1), (target.y as u32).min(self.target.height - 1));
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Pixel readback"),
+        });
+        let readback = Readback::new(&self.device, &mut encoder, &self.target.texture, (x, y), 1, 1);
+        self.queue.submit(iter::once(encoder.finish()));
+
+        let pixel = readback.read(&self.device, swizzle)?;
+        Ok([pixel[0], pixel[1], pixel[2], pixel[3]])
+    }
+
+    /// Copies `range` of `buffer` out to the CPU, blocking until the GPU is done with any submitted work writing to
+    /// it. The buffer needs `MAP_READ` usage, and is unmapped again afterwards. Not available on the web, which can't
+    /// block; see [`State::read_buffer_async`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_buffer(
+        &self, buffer: &wgpu::Buffer, range: impl RangeBounds<wgpu::BufferAddress>,
+    ) -> Result<Vec<u8>, AndError> {
+        capture::read_buffer(&self.device, buffer, range).block_on()
+    }
+
+    /// [`State::read_buffer`] for the web, resolving once the browser finished mapping. It works on native as well,
+    /// but there the first poll waits on the device just like [`State::read_buffer`].
+    pub fn read_buffer_async<'a>(
+        &'a self, buffer: &'a wgpu::Buffer, range: impl RangeBounds<wgpu::BufferAddress> + 'a,
+    ) -> impl Future<Output = Result<Vec<u8>, AndError>> + 'a {
+        capture::read_buffer(&self.device, buffer, range)
+    }
+
+    /// Reads back the whole last rendered frame. Blocks until the GPU is done with the copy.
+    pub fn capture_frame(&self) -> Result<image::RgbaImage, AndError> {
+        let swizzle = capture::swizzle(self.scene_format)?;
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Frame capture"),
+        });
+        let (width, height) = (self.target.width, self.target.height);
+        let readback = Readback::new(&self.device, &mut encoder, &self.target.texture, (0, 0), width, height);
+        self.queue.submit(iter::once(encoder.finish()));
+
+        readback.read_image(&self.device, swizzle)
+    }
+
+    /// Writes the next `frame_count` frames to `frameNNNN.png` files in `dir`, creating it if needed. Keeps redrawing
+    /// continuously until done. Replaces any recording already in progress.
+    pub fn start_recording(&mut self, dir: impl AsRef<Path>, frame_count: u32) -> Result<(), AndError> {
+        let swizzle = capture::swizzle(self.scene_format)?;
+        self.recorder = Some(Recorder::new(self.device.clone(), dir.as_ref(), frame_count, swizzle)?);
+        self.window.request_redraw();
+        Ok(())
+    }
+
+    /// Plays an encoded (WAV or Ogg Vorbis) sound once, mixed over whatever else is playing. Does nothing if there's
+    /// no audio device.
+    #[cfg(feature = "audio")]
+    pub fn play_sound(&self, data: &[u8]) -> Result<(), AndError> {
+        match self.audio.as_ref() {
+            Some(audio) => audio.play_sound(data),
+            None => Ok(()),
+        }
+    }
+
+    /// Loops an encoded music track, replacing the one already playing. Does nothing if there's no audio device.
+    #[cfg(feature = "audio")]
+    pub fn play_music(&mut self, data: Vec<u8>) -> Result<(), AndError> {
+        match self.audio.as_mut() {
+            Some(audio) => audio.play_music(data),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "audio")]
+    pub fn stop_music(&mut self) {
+        if let Some(audio) = self.audio.as_mut() {
+            audio.stop_music();
+        }
+    }
+
+    #[cfg(feature = "audio")]
+    pub fn set_music_volume(&mut self, volume: f32) {
+        if let Some(audio) = self.audio.as_mut() {
+            audio.set_music_volume(volume);
+        }
+    }
+
+    /// Whether the compositor reported the window as entirely hidden, e.g. behind other windows or on another
+    /// virtual desktop. Nothing is rendered until it's visible again, including frames that'd otherwise redraw
+    /// continuously.
+    pub fn is_occluded(&self) -> bool {
+        self.occluded
+    }
+
+    fn occluded(&mut self, occluded: bool) {
+        if occluded == self.occluded { return };
+
+        log::debug!("Window {}", if occluded { "occluded, pausing rendering" } else { "visible again" });
+        self.occluded = occluded;
+        // The surface may have gone stale while hidden, same as after a focus change.
+        if !occluded {
+            self.configure_surface();
+            self.window.request_redraw();
+        }
+    }
+
+    /// Restores the cursor grab, which most platforms release when the window loses focus. Also pauses all audio
+    /// playback while the window is unfocused, which includes being minimized. Backgrounding on Android suspends the
+    /// app instead, dropping the audio output along with everything else.
+    fn focused(&mut self, focused: bool) {
+        let regained = focused && !self.focused;
+        self.focused = focused;
+        if !focused {
+            self.input.release_all();
+        }
+
+        // Some platforms leave the surface outdated without a resize, notably after switching away from fullscreen,
+        // which would freeze the last frame until something else reconfigures it.
+        if regained {
+            let PhysicalSize { width, height, } = self.window.inner_size();
+            if (width, height) != (self.config.width, self.config.height) {
+                self.resize(width, height);
+            } else if self.window.fullscreen().is_some() {
+                self.configure_surface();
+            }
+            self.window.request_redraw();
+        }
+        if focused && self.cursor_grab != CursorGrabMode::None {
+            self.set_cursor_grab(self.cursor_grab);
+        }
+
+        #[cfg(feature = "audio")]
+        if let Some(audio) = self.audio.as_ref() {
+            audio.set_paused(!focused);
+        }
+    }
+
+    /// Grabs the cursor, or releases it with [`CursorGrabMode::None`]. Platforms tend to support only one of
+    /// `Confined` and `Locked`, so the other one is tried if the requested one fails. Returns the mode that took,
+    /// which is reapplied whenever the window regains focus.
+    pub fn set_cursor_grab(&mut self, mode: CursorGrabMode) -> CursorGrabMode {
+        let fallback = match mode {
+            CursorGrabMode::None => None,
+            CursorGrabMode::Confined => Some(CursorGrabMode::Locked),
+            CursorGrabMode::Locked => Some(CursorGrabMode::Confined),
+        };
+
+        let applied = match self.window.set_cursor_grab(mode) {
+            Ok(()) => mode,
+            Err(e) => match fallback.map(|fallback| (fallback, self.window.set_cursor_grab(fallback))) {
+                Some((fallback, Ok(()))) => {
+                    log::info!("Cursor grab {mode:?} isn't available ({e}), using {fallback:?}");
+                    fallback
+                },
+                Some((_, Err(fallback_err))) => {
+                    log::warn!("Couldn't grab the cursor: {e}, {fallback_err}");
+                    CursorGrabMode::None
+                },
+                None => {
+                    log::warn!("Couldn't release the cursor: {e}");
+                    self.cursor_grab
+                },
+            },
+        };
+
+        self.cursor_grab = applied;
+        applied
+    }
+
+    pub fn cursor_grab(&self) -> CursorGrabMode {
+        self.cursor_grab
+    }
+
+    /// Where the scene shows up on the surface, in physical pixels: all of it, or the largest rectangle of
+    /// [`Config::aspect`] centered in it.
+    pub fn content_rect(&self) -> Rect {
+        letterbox(glam::Vec2::new(self.config.width as f32, self.config.height as f32), self.user_config.aspect)
+    }
+
+    fn content_size(&self) -> (u32, u32) {
+        let size = self.content_rect().size;
+        (size.x as u32, size.y as u32)
+    }
+
+    /// Letterboxes the scene to `aspect`, or stretches it over the window again if `None`; see [`Config::aspect`].
+    pub fn set_aspect(&mut self, aspect: Option<f32>) {
+        if self.user_config.aspect == aspect { return };
+
+        self.user_config.aspect = aspect;
+        self.resize_target();
+    }
+
+    /// Size the scene is rendered at: the content's, times the render scale.
+    fn target_size(&self) -> (u32, u32) {
+        let (width, height) = self.content_size();
+        let scaled = |size: u32| ((size as f32 * self.render_scale).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+
+    fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        let position = glam::Vec2::new(position.x as f32, position.y as f32);
+        self.input.cursor_position = Some(position - self.content_rect().position);
+    }
+
+    /// Recreates everything sized after the render resolution.
+    fn resize_target(&mut self) {
+        let (width, height) = self.target_size();
+        let (format, depth_format, samples) = (self.scene_format, self.depth_format(), self.user_config.msaa_samples);
+        self.target = FrameTarget::new(&self.device, &self.blit, format, depth_format, samples, width, height);
+        if let Some(bloom) = self.bloom.as_mut() {
+            bloom.resize(&self.device, &self.target.view, width, height);
+        }
+        if let Some(accumulation) = self.accumulation.as_mut() {
+            accumulation.resize(&self.device, &self.blit, width, height);
+        }
+    }
+
+    /// Starts [`Config::accumulation_frames`] over from the next frame, e.g. because the scene changed in a way the
+    /// camera doesn't capture.
+    pub fn reset_accumulation(&mut self) {
+        if let Some(accumulation) = self.accumulation.as_mut() {
+            accumulation.reset();
+        }
+    }
+
+    /// Frames averaged into what's presented since accumulation last started over, 0 if it's off.
+    pub fn accumulated_frames(&self) -> u32 {
+        self.accumulation.as_ref().map_or(0, Accumulation::frames)
+    }
+
+    /// CPU time of the last [`Config::frame_time_history`] frames in milliseconds, oldest first, e.g. for plotting a
+    /// frame time graph. Measured from the start of [`Config::on_frame`] until the frame is presented.
+    pub fn frame_time_history(&self) -> &[f32] {
+        self.frame_times.as_slice()
+    }
+
+    /// Command buffers and render passes of the last frame, along with any [`State::render_to_texture`] calls
+    /// since the frame before it.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Renders the scene at `scale` times the surface resolution, upscaling (or downscaling, above 1) when
+    /// presenting. Readbacks such as [`State::capture_frame`] are at the render resolution. Overridden over time by
+    /// [`Config::adaptive_resolution`] if set.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        let scale = scale.clamp(0.1, 2.0);
+        if scale == self.render_scale { return };
+
+        self.render_scale = scale;
+        self.resize_target();
+    }
+
+    /// Textures and buffers the crate currently holds, with their approximate sizes. Surface textures aren't counted.
+    pub fn resource_usage(&self) -> ResourceUsage {
+        resources::usage()
+    }
+
+    /// Logs [`State::resource_usage`], e.g. to check that resizing doesn't leak offscreen targets.
+    pub fn report_resources(&self) {
+        log::info!("Resources in use: {}", self.resource_usage());
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 { return };
+        if self.awaiting_size {
+            log::debug!("Got a {width}x{height} size, starting to render");
+            self.awaiting_size = false;
+            self.window.request_redraw();
+        }
+        if width == self.config.width && height == self.config.height { return };
+
+        self.config.width = width;
+        self.config.height = height;
+        self.configure_surface();
+        self.resize_target();
+
+        if let Some(on_resize) = self.user_config.on_resize.as_mut() {
+            on_resize(width, height);
+        }
+    }
+}
+
+fn init_logger(level: log::LevelFilter) {
+    // `init_once` already tolerates an existing logger. The environment is parsed after the configured level, so it
+    // overrides it like on desktop.
+    #[cfg(target_os = "android")]
+    {
+        let mut filter = android_logger::FilterBuilder::new();
+        filter.filter_level(level);
+        if let Ok(spec) = std::env::var("RUST_LOG") {
+            filter.parse(&spec);
+        }
+
+        let filter = filter.build();
+        if let Some(max_level) = filter.filter().to_level() {
+            android_logger::init_once(android_logger::Config::default()
+                .with_min_level(max_level)
+                .with_filter(filter)
+            );
+        }
+    }
+
+    #[cfg(not(target_os = "android"))]
+    if env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .try_init()
+        .is_err()
+    {
+        log::debug!("A logger is already set, keeping it");
+    }
+}
+
+/// Runs with a default event loop; see [`run_with_event_loop`].
+pub fn run(config: Config) {
+    run_with_event_loop(event_loop(&config), config);
+}
+
+/// Builds the event loop [`run`] and [`run_from_settings`] use, as per [`Config::any_thread`].
+fn event_loop(config: &Config) -> EventLoop<UserEvent> {
+    let mut builder = EventLoopBuilder::with_user_event();
+    #[cfg(target_os = "windows")]
+    winit::platform::windows::EventLoopBuilderExtWindows::with_any_thread(&mut builder, config.any_thread);
+    #[cfg(any(
+        target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    winit::platform::unix::EventLoopBuilderExtUnix::with_any_thread(&mut builder, config.any_thread);
+    #[cfg(not(any(
+        target_os = "windows", target_os = "linux", target_os = "dragonfly", target_os = "freebsd",
+        target_os = "netbsd", target_os = "openbsd",
+    )))]
+    let _ = config;
+
+    builder.build()
+}
+
+/// Runs with [`Settings`] loaded from the TOML file at `path` applied over `config`, writing the defaults there if it
+/// doesn't exist yet. The settings are saved back to it on exit.
+#[cfg(feature = "settings")]
+pub fn run_from_settings(path: impl AsRef<Path>, mut config: Config) {
+    let path = path.as_ref().to_path_buf();
+    match Settings::load(&path) {
+        Ok(settings) => settings.apply(&mut config),
+        Err(e) => log::error!("Couldn't load settings from {}, using defaults: {e}", path.display()),
+    }
+
+    run_event_loop(event_loop(&config), config, move |config| {
+        if let Err(e) = Settings::from_config(config).save(&path) {
+            log::error!("Couldn't save settings to {}: {e}", path.display());
+        }
+    });
+}
+
+/// Runs on an event loop built by the caller, e.g. through [`winit::event_loop::EventLoopBuilder`] with
+/// platform-specific options.
+pub fn run_with_event_loop(event_loop: EventLoop<UserEvent>, config: Config) {
+    run_event_loop(event_loop, config, |_| {});
+}
+
+fn run_event_loop(event_loop: EventLoop<UserEvent>, config: Config, on_exit: impl FnOnce(&Config) + 'static) {
+    if config.init_logger {
+        init_logger(config.log_level);
+    }
+    #[cfg(not(any(
+        target_os = "windows", target_os = "linux", target_os = "dragonfly", target_os = "freebsd",
+        target_os = "netbsd", target_os = "openbsd",
+    )))]
+    if config.any_thread {
+        log::warn!("Running the event loop off the main thread isn't supported here, ignoring Config::any_thread");
+    }
+
+    let proxy = event_loop.create_proxy();
+    let mut config = Some(config);
+    let mut state: Option<State> = None;
+    let mut on_exit = Some(on_exit);
+
+    event_loop.run(move |event, event_loop, control_flow| {
+        match event {
+            Event::NewEvents(cause) => {
+                if let StartCause::Init = cause {
+                    log::info!("Hello, world!");
+                }
+
+                if state.as_ref().is_some_and(State::recovery_due) {
+                    state = state.take().and_then(State::recover);
+                    if state.is_none() {
+                        log::error!("Couldn't recover from device loss, giving up");
+                        *control_flow = ControlFlow::ExitWithCode(1);
+                    }
+                }
+
+                if let Some(st) = state.as_mut() {
+                    st.new_events();
+                }
+            },
+            Event::Resumed => {
+                log::info!("Hello again, world!");
+                if let Some(config) = config.take() {
+                    match State::new(event_loop, proxy.clone(), config) {
+                        Ok(st) => {
+                            if !st.awaiting_size {
+                                st.window.request_redraw();
+                            }
+                            state = Some(st);
+                        },
+                        Err(e) => {
+                            log::error!("Couldn't initialize: {e}");
+                            *control_flow = ControlFlow::ExitWithCode(1);
+                        },
+                    }
+                }
+            },
+            Event::Suspended => {
+                log::info!("Where are you going, world?");
+                if let Some(st) = state.take() {
+                    config = Some(st.user_config);
+                }
+            },
+            Event::WindowEvent { window_id, event } => {
+                let Some(st) = state.as_mut() else { return };
+                if window_id != st.window.id() { return };
+
+                match event {
+                    WindowEvent::CloseRequested => *control_flow = ControlFlow::ExitWithCode(0),
+                    // The surface and everything else GPU-side are unusable until the device is recovered.
+                    _ if st.recovery.is_some() => {},
+                    event => st.window_event(event),
+                }
+            },
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta, }, .. } => {
+                if let Some(st) = state.as_mut() {
+                    st.mouse_motion(delta);
+                }
+            },
+            Event::RedrawRequested(window_id) => {
+                let Some(st) = state.as_mut() else { return };
+                if window_id != st.window.id() || st.recovery.is_some() { return };
+
+                // A failure left over from `State::render_now` is handled here, since only the loop can act on it.
+                let result = match st.failure.take() {
+                    Some(failure) => Err(failure),
+                    None => st.present_frame(),
+                };
+                match result {
+                    Ok(()) => {},
+                    Err(FrameFailure::DeviceLost) => {
+                        log::warn!("Reconfiguring doesn't fix the lost surface, assuming the device is gone");
+                        st.lose_device();
+                    },
+                    Err(FrameFailure::OutOfMemory) => *control_flow = ControlFlow::ExitWithCode(1),
+                }
+            },
+            Event::UserEvent(event) => {
+                if let Some(st) = state.as_mut() {
+                    st.user_event(event);
+                }
+            },
+            // Set once per iteration, as every later event of it leaves the flow be; an exit requested by an
+            // earlier one sticks.
+            Event::MainEventsCleared if !matches!(*control_flow, ControlFlow::ExitWithCode(_)) => {
+                *control_flow = state.as_mut().map_or(ControlFlow::Wait, State::next_control_flow);
+            },
+            Event::LoopDestroyed => {
+                let last_config = state.take().map(|st| st.user_config).or_else(|| config.take());
+                if let (Some(on_exit), Some(last_config)) = (on_exit.take(), last_config) {
+                    on_exit(&last_config);
+                }
+
+                log::info!("Goodbye, world!");
+            },
+            _ => {},
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transparent_alpha_modes() {
+        use wgpu::CompositeAlphaMode::*;
+        assert_eq!(alpha_mode(false, &[Opaque, PreMultiplied]), Auto);
+        assert_eq!(alpha_mode(true, &[Opaque, PostMultiplied, PreMultiplied]), PreMultiplied);
+        assert_eq!(alpha_mode(true, &[Opaque, Inherit, PostMultiplied]), PostMultiplied);
+        assert_eq!(alpha_mode(true, &[Opaque]), Auto);
+    }
+
+    #[test]
+    fn premultiplied_clear_color() {
+        let color = wgpu::Color { r: 1.0, g: 0.5, b: 0.0, a: 0.5, };
+        let premultiplied = wgpu::Color { r: 0.5, g: 0.25, b: 0.0, a: 0.5, };
+        assert_eq!(surface_color(color, wgpu::CompositeAlphaMode::PreMultiplied), premultiplied);
+        for mode in [wgpu::CompositeAlphaMode::Auto, wgpu::CompositeAlphaMode::PostMultiplied] {
+            assert_eq!(surface_color(color, mode), color, "{mode:?}");
+        }
+    }
+
+    fn resolve(
+        policy: PresentModePolicy, mode: wgpu::PresentMode, supported: &[wgpu::PresentMode],
+    ) -> wgpu::PresentMode {
+        present_mode(&Config { present_mode_policy: policy, present_mode: mode, ..Config::default() }, supported)
+    }
+
+    #[test]
+    fn present_mode_policies() {
+        use wgpu::PresentMode::*;
+        let all = [Fifo, Mailbox, Immediate];
+        assert_eq!(resolve(PresentModePolicy::Smooth, Fifo, &all), Mailbox);
+        assert_eq!(resolve(PresentModePolicy::LowLatency, Fifo, &all), Mailbox);
+        assert_eq!(resolve(PresentModePolicy::PowerSaving, Immediate, &all), Fifo);
+    }
+
+    #[test]
+    fn present_mode_policy_fallbacks() {
+        use wgpu::PresentMode::*;
+        assert_eq!(resolve(PresentModePolicy::Smooth, Immediate, &[Fifo, Immediate]), Fifo);
+        assert_eq!(resolve(PresentModePolicy::LowLatency, Fifo, &[Fifo, Immediate]), Immediate);
+        assert_eq!(resolve(PresentModePolicy::LowLatency, Mailbox, &[Fifo]), Fifo);
+        assert_eq!(resolve(PresentModePolicy::Smooth, Mailbox, &[]), Fifo);
+    }
+
+    #[test]
+    fn requested_present_mode() {
+        use wgpu::PresentMode::*;
+        assert_eq!(resolve(PresentModePolicy::Auto, Immediate, &[Fifo, Immediate]), Immediate);
+        assert_eq!(resolve(PresentModePolicy::Auto, Mailbox, &[Fifo, Immediate]), Fifo);
+        // Resolved by wgpu itself, so never checked against the list.
+        assert_eq!(resolve(PresentModePolicy::Auto, AutoNoVsync, &[]), AutoNoVsync);
+        assert_eq!(resolve(PresentModePolicy::Auto, AutoVsync, &[Fifo]), AutoVsync);
+    }
+
+    #[test]
+    fn letterbox_without_aspect() {
+        let surface = glam::Vec2::new(800.0, 600.0);
+        for aspect in [None, Some(0.0), Some(-1.0), Some(f32::NAN), Some(f32::INFINITY)] {
+            assert_eq!(letterbox(surface, aspect), Rect::new(0.0, 0.0, 800.0, 600.0), "{aspect:?}");
+        }
+    }
+
+    #[test]
+    fn letterbox_bars() {
+        // Pillarboxed on a wider surface, letterboxed on a taller one, and untouched at the same aspect ratio.
+        assert_eq!(letterbox(glam::Vec2::new(1000.0, 500.0), Some(1.0)), Rect::new(250.0, 0.0, 500.0, 500.0));
+        assert_eq!(letterbox(glam::Vec2::new(400.0, 600.0), Some(2.0)), Rect::new(0.0, 200.0, 400.0, 200.0));
+        assert_eq!(letterbox(glam::Vec2::new(1600.0, 900.0), Some(16.0 / 9.0)), Rect::new(0.0, 0.0, 1600.0, 900.0));
+    }
+
+    #[test]
+    fn letterbox_whole_pixels() {
+        // 301 / 1.5 = 200.67 rounds up, leaving an odd bar that's floored.
+        assert_eq!(letterbox(glam::Vec2::new(301.0, 300.0), Some(1.5)), Rect::new(0.0, 49.0, 301.0, 201.0));
+        assert_eq!(letterbox(glam::Vec2::new(1.0, 1.0), Some(100.0)), Rect::new(0.0, 0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn multisample_counts_need_resolve_for_color() {
+        use wgpu::TextureFormatFeatureFlags as Flags;
+        let color = Flags::MULTISAMPLE | Flags::MULTISAMPLE_RESOLVE | Flags::FILTERABLE;
+        assert_eq!(multisample_counts(color, false), [1, 4]);
+        assert_eq!(multisample_counts(Flags::MULTISAMPLE, true), [1, 4]);
+        // Color that can't be resolved, or nothing that can't be multisampled.
+        assert_eq!(multisample_counts(Flags::MULTISAMPLE, false), [1]);
+        assert_eq!(multisample_counts(Flags::empty(), false), [1]);
+        assert_eq!(multisample_counts(Flags::empty(), true), [1]);
+    }
+
+    fn snap(width: u32, height: u32, increments: Option<(u32, u32)>) -> PhysicalSize<u32> {
+        snap_to_increments(PhysicalSize::new(width, height), increments)
+    }
+
+    #[test]
+    fn snaps_down_to_whole_cells() {
+        assert_eq!(snap(805, 610, None), PhysicalSize::new(805, 610));
+        assert_eq!(snap(800, 600, Some((8, 16))), PhysicalSize::new(800, 592));
+        assert_eq!(snap(805, 610, Some((10, 10))), PhysicalSize::new(800, 610));
+    }
+
+    #[test]
+    fn snaps_to_at_least_one_cell() {
+        assert_eq!(snap(5, 3, Some((8, 16))), PhysicalSize::new(8, 16));
+        assert_eq!(snap(0, 0, Some((8, 16))), PhysicalSize::new(8, 16));
+        // A zero increment leaves that axis alone instead of dividing by it.
+        assert_eq!(snap(805, 610, Some((0, 10))), PhysicalSize::new(805, 610));
+    }
 }
@@ -0,0 +1,95 @@
+use crate::resources::{
+    self,
+    Tracked,
+};
+use bytemuck::{
+    Pod, Zeroable,
+};
+use glam::Vec3;
+
+/// A single light shining from infinitely far away, such as the sun, shading meshes that have normals.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    /// The way the light travels, from the light towards the scene; normalized when uploaded.
+    pub direction: Vec3,
+    /// Linear, scaling the diffuse term.
+    pub color: [f32; 3],
+    /// Added to every surface regardless of its normal, so faces turned away from the light aren't pitch black.
+    pub ambient: [f32; 3],
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self { direction: Vec3::new(-0.3, -1.0, -0.5), color: [0.8; 3], ambient: [0.2; 3], }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct LightUniform {
+    direction: [f32; 3],
+    _pad: f32,
+    color: [f32; 3],
+    _pad2: f32,
+    ambient: [f32; 3],
+    _pad3: f32,
+}
+
+impl LightUniform {
+    /// Without a light, a full ambient term and no diffuse one leave colors as they are.
+    fn new(light: Option<DirectionalLight>) -> Self {
+        let (direction, color, ambient) = match light {
+            Some(light) => (light.direction.normalize_or_zero().into(), light.color, light.ambient),
+            None => ([0.0; 3], [0.0; 3], [1.0; 3]),
+        };
+        Self { direction, _pad: 0.0, color, _pad2: 0.0, ambient, _pad3: 0.0, }
+    }
+}
+
+/// The light of the mesh pipelines, bound at group 2.
+pub struct Light {
+    buffer: Tracked<wgpu::Buffer>,
+    pub layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Light {
+    pub fn new(device: &wgpu::Device, light: Option<DirectionalLight>) -> Self {
+        let buffer = resources::create_buffer_init(device, &wgpu::util::BufferInitDescriptor {
+            label: Some("Light uniform"),
+            contents: bytemuck::bytes_of(&LightUniform::new(light)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        Self { buffer, layout, bind_group, }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, light: Option<DirectionalLight>) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&LightUniform::new(light)));
+    }
+
+    pub fn bind<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, group: u32) {
+        pass.set_bind_group(group, &self.bind_group, &[]);
+    }
+}
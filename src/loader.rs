@@ -0,0 +1,313 @@
+use crate::{
+    resources::{
+        self,
+        Tracked,
+    },
+    AndError, UserEvent,
+};
+use winit::event_loop::EventLoopProxy;
+use std::{
+    any::Any,
+    collections::HashMap,
+    panic::{
+        self,
+        AssertUnwindSafe,
+    },
+    path::PathBuf,
+    sync::{
+        atomic::{
+            AtomicBool, AtomicU64, Ordering,
+        },
+        mpsc::{
+            self,
+            Receiver, Sender,
+        },
+        Arc,
+    },
+    thread,
+};
+
+// Global rather than per loader, so handles stay unique across loaders recreated by device loss recovery.
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(0);
+
+/// Refers to a texture requested through [`State::load_texture_async`](crate::State::load_texture_async), which
+/// may or may not have finished loading yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextureHandle(u64);
+
+pub struct Texture {
+    pub texture: Tracked<wgpu::Texture>,
+    pub view: wgpu::TextureView,
+    pub width: u32,
+    pub height: u32,
+    /// Whether its color is multiplied by its alpha, meaning it should be drawn with
+    /// [`BlendMode::Premultiplied`](crate::BlendMode::Premultiplied) rather than the default straight alpha blending.
+    pub premultiplied: bool,
+}
+
+/// Tightly packed RGBA8 pixels decoded by a job passed to
+/// [`State::stream_texture`](crate::State::stream_texture), ready to be uploaded.
+#[derive(Debug, Clone)]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    /// Whether `rgba` is sRGB-encoded, as images usually are, or linear, as e.g. normal maps are.
+    pub srgb: bool,
+    /// Whether `rgba` is already multiplied by alpha; see [`DecodedImage::premultiply`].
+    pub premultiplied: bool,
+    pub rgba: Vec<u8>,
+}
+
+impl DecodedImage {
+    /// Multiplies the color by alpha, unless it already is.
+    pub fn premultiply(&mut self) {
+        if self.premultiplied { return };
+
+        premultiply_alpha(&mut self.rgba, self.srgb);
+        self.premultiplied = true;
+    }
+}
+
+/// Multiplies the color of tightly packed RGBA8 pixels by their alpha. For sRGB pixels that happens on their linear
+/// values, which is what blending sees once the GPU has decoded them.
+pub fn premultiply_alpha(rgba: &mut [u8], srgb: bool) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let alpha = pixel[3] as f32 / 255.0;
+        if alpha >= 1.0 { continue };
+
+        for channel in &mut pixel[..3] {
+            let value = *channel as f32 / 255.0;
+            let value = if srgb { linear_to_srgb(srgb_to_linear(value) * alpha) } else { value * alpha };
+            *channel = (value * 255.0).round() as u8;
+        }
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 }
+}
+
+type Job = Box<dyn FnOnce(&wgpu::Device, &wgpu::Queue) -> Result<Texture, AndError> + Send>;
+
+/// Decodes images and uploads them on a background thread, so the frame loop never waits on disk or decoding.
+/// Textures finish in the order they were requested.
+pub struct Loader {
+    jobs: Sender<(TextureHandle, Job)>,
+    done: Receiver<(TextureHandle, Result<Texture, AndError>)>,
+    textures: HashMap<TextureHandle, Texture>,
+    /// Why the textures that didn't make it failed, kept until they're removed.
+    failures: HashMap<TextureHandle, AndError>,
+    /// Set once this loader's device is no longer used, so the thread skips whatever is still queued for it.
+    abandoned: Arc<AtomicBool>,
+}
+
+impl Loader {
+    /// Wakes the event loop through `proxy` whenever a texture finishes, so it gets picked up on the next frame.
+    pub fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>, proxy: EventLoopProxy<UserEvent>) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<(TextureHandle, Job)>();
+        let (done_sender, done) = mpsc::channel();
+        let abandoned = Arc::new(AtomicBool::new(false));
+
+        let thread_abandoned = abandoned.clone();
+        thread::Builder::new()
+            .name("And texture loader".to_string())
+            .spawn(move || {
+                for (handle, job) in job_receiver {
+                    if thread_abandoned.load(Ordering::Relaxed) { break };
+
+                    // A panicking job, e.g. a user's decode closure, only fails its own texture.
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| job(&device, &queue)))
+                        .unwrap_or_else(|payload| Err(AndError::LoaderPanic(panic_message(payload))));
+                    if done_sender.send((handle, result)).is_err() { break };
+                    drop(proxy.send_event(UserEvent::Redraw));
+                }
+            })
+            .expect("Couldn't spawn texture loader thread");
+
+        Self { jobs, done, textures: HashMap::new(), failures: HashMap::new(), abandoned, }
+    }
+
+    pub fn request(&mut self, path: PathBuf, premultiply: bool) -> Result<TextureHandle, AndError> {
+        self.queue(Box::new(move |device, queue| load(device, queue, &path, premultiply)))
+    }
+
+    /// Runs `decode` on the loader thread and uploads what it yields from there as well.
+    pub fn stream(
+        &mut self, decode: impl FnOnce() -> Result<DecodedImage, AndError> + Send + 'static,
+    ) -> Result<TextureHandle, AndError> {
+        self.queue(Box::new(move |device, queue| {
+            let image = decode()?;
+            let expected = image.width as usize * image.height as usize * 4;
+            if image.rgba.len() != expected {
+                return Err(AndError::InvalidImage(format!(
+                    "{} bytes of pixels for a {}x{} RGBA image, expected {expected}",
+                    image.rgba.len(), image.width, image.height,
+                )));
+            }
+
+            let format = if image.srgb { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm };
+            let (width, height) = (image.width, image.height);
+            let texture = create_with_format(
+                device, queue, Some("Streamed texture"), format, width, height, &image.rgba,
+            );
+            Ok(Texture { premultiplied: image.premultiplied, ..texture })
+        }))
+    }
+
+    fn queue(&mut self, job: Job) -> Result<TextureHandle, AndError> {
+        let handle = next_handle();
+        self.jobs.send((handle, job)).map_err(|_| AndError::LoaderStopped)?;
+        Ok(handle)
+    }
+
+    /// Adds an already created texture, skipping the loader thread.
+    pub fn insert(&mut self, texture: Texture) -> TextureHandle {
+        let handle = next_handle();
+        self.textures.insert(handle, texture);
+        handle
+    }
+
+    /// Collects every texture finished since the last call, returning their handles. Called once per frame.
+    pub fn poll(&mut self) -> Vec<TextureHandle> {
+        let mut arrived = Vec::new();
+        for (handle, result) in self.done.try_iter() {
+            match result {
+                Ok(texture) => {
+                    self.textures.insert(handle, texture);
+                    arrived.push(handle);
+                },
+                Err(e) => {
+                    log::error!("Couldn't load texture {handle:?}: {e}");
+                    self.failures.insert(handle, e);
+                },
+            }
+        }
+
+        arrived
+    }
+
+    pub fn remove(&mut self, handle: TextureHandle) -> Option<Texture> {
+        self.failures.remove(&handle);
+        self.textures.remove(&handle)
+    }
+
+    pub fn failure(&self, handle: TextureHandle) -> Option<&AndError> {
+        self.failures.get(&handle)
+    }
+
+    pub fn get(&self, handle: TextureHandle) -> Option<&Texture> {
+        self.textures.get(&handle)
+    }
+}
+
+impl Drop for Loader {
+    /// Whatever the thread finishes from here on would be thrown away, and its device may well be lost, so it stops
+    /// after the job it's on.
+    fn drop(&mut self) {
+        self.abandoned.store(true, Ordering::Relaxed);
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("no message", |message| message).to_string(),
+    }
+}
+
+pub(crate) fn next_handle() -> TextureHandle {
+    TextureHandle(NEXT_HANDLE.fetch_add(1, Ordering::Relaxed))
+}
+
+fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: &PathBuf, premultiply: bool) -> Result<Texture, AndError> {
+    #[cfg(feature = "ktx2")]
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("ktx2")) {
+        if premultiply {
+            log::warn!("Block-compressed textures can't be premultiplied on upload, loading {} as is", path.display());
+        }
+        return crate::compressed::load_ktx2(device, queue, path);
+    }
+
+    let mut img = image::open(path)?.into_rgba8();
+    let (width, height) = img.dimensions();
+    if premultiply {
+        premultiply_alpha(&mut img, true);
+    }
+
+    let texture = create(device, queue, path.to_str(), width, height, img.as_raw());
+    Ok(Texture { premultiplied: premultiply, ..texture })
+}
+
+/// Uploads tightly packed sRGB RGBA pixels into a sampleable texture.
+pub fn create(
+    device: &wgpu::Device, queue: &wgpu::Queue, label: Option<&str>, width: u32, height: u32, rgba: &[u8],
+) -> Texture {
+    create_with_format(device, queue, label, wgpu::TextureFormat::Rgba8UnormSrgb, width, height, rgba)
+}
+
+/// Uploads tightly packed RGBA8 pixels, in either the sRGB or linear variant of the format.
+pub fn create_with_format(
+    device: &wgpu::Device, queue: &wgpu::Queue, label: Option<&str>, format: wgpu::TextureFormat,
+    width: u32, height: u32, rgba: &[u8],
+) -> Texture {
+    let texture = resources::create_texture_with_data(device, queue, &wgpu::TextureDescriptor {
+        label,
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1, },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    }, rgba);
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    Texture { texture, view, width, height, premultiplied: false, }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn premultiplies_linear() {
+        let mut rgba = [200, 100, 50, 128, 255, 255, 255, 0];
+        premultiply_alpha(&mut rgba, false);
+        assert_eq!(rgba, [100, 50, 25, 128, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn premultiplies_srgb_in_linear() {
+        // Half of white's linear value, encoded back, is brighter than half of its encoded value.
+        let mut rgba = [255, 255, 255, 128];
+        premultiply_alpha(&mut rgba, true);
+        assert_eq!(rgba, [188, 188, 188, 128]);
+    }
+
+    #[test]
+    fn keeps_opaque_pixels() {
+        let mut rgba = [12, 34, 56, 255];
+        premultiply_alpha(&mut rgba, true);
+        assert_eq!(rgba, [12, 34, 56, 255]);
+    }
+
+    #[test]
+    fn ignores_trailing_bytes() {
+        let mut rgba = [200, 200, 200, 0, 7, 7];
+        premultiply_alpha(&mut rgba, false);
+        assert_eq!(rgba, [0, 0, 0, 0, 7, 7]);
+        premultiply_alpha(&mut [], false);
+    }
+
+    #[test]
+    fn premultiplies_once() {
+        let rgba = vec![200, 0, 0, 128];
+        let mut image = DecodedImage { width: 1, height: 1, srgb: false, premultiplied: false, rgba, };
+        image.premultiply();
+        image.premultiply();
+        assert_eq!((image.rgba.as_slice(), image.premultiplied), ([100, 0, 0, 128].as_slice(), true));
+    }
+}
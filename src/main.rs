@@ -1,3 +1,3 @@
 fn main() {
-    and::run();
+    and::run(and::Config::default());
 }
@@ -0,0 +1,467 @@
+use crate::{
+    resources::{
+        self,
+        Tracked,
+    },
+    upload::{
+        self,
+        Uploader,
+    },
+    AndError,
+};
+use bytemuck::{
+    Pod, Zeroable,
+};
+use glam::{
+    Mat4, Vec3,
+};
+use std::ops::Range;
+
+/// A vertex in the layout [`crate::State::set_mesh`] takes and loaded models come in, at shader locations 0 to 3 in
+/// field order. The scene shader only reads the position, and the normal for [`crate::Config::light`].
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    /// Straight, not premultiplied, alpha.
+    pub color: [f32; 4],
+}
+
+impl Vertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        0 => Float32x3, 1 => Float32x3, 2 => Float32x2, 3 => Float32x4,
+    ];
+
+    pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// A range of a mesh's indices that can be drawn on its own, e.g. one per object or material of a loaded model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submesh {
+    pub name: String,
+    /// Index into whatever materials came with the mesh, if any.
+    pub material: Option<usize>,
+    pub indices: Range<u32>,
+}
+
+/// Indexed geometry drawn in place of the placeholder triangle, with vertices in whatever layout the caller uploaded
+/// them in. The scene shader only reads a `Float32x3` position at shader location 0, and a `Float32x3` normal at
+/// location 1 if there is one; other attributes are carried along for pipelines registered through
+/// [`crate::State::register_pipeline`].
+pub struct Mesh {
+    vertices: Tracked<wgpu::Buffer>,
+    indices: Tracked<wgpu::Buffer>,
+    index_count: u32,
+    array_stride: wgpu::BufferAddress,
+    attributes: Vec<wgpu::VertexAttribute>,
+    submeshes: Vec<Submesh>,
+}
+
+impl Mesh {
+    pub fn new(
+        device: &wgpu::Device, vertices: &[u8], layout: &wgpu::VertexBufferLayout, indices: &[u32],
+    ) -> Result<Self, AndError> {
+        validate(&device.limits(), vertices, layout, indices).map_err(AndError::InvalidMesh)?;
+
+        let vertices = resources::create_buffer_init(device, &wgpu::util::BufferInitDescriptor {
+            label: Some("Mesh vertices"),
+            contents: vertices,
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_count = indices.len() as u32;
+        let indices = resources::create_buffer_init(device, &wgpu::util::BufferInitDescriptor {
+            label: Some("Mesh indices"),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Ok(Self {
+            vertices, indices, index_count,
+            array_stride: layout.array_stride,
+            attributes: layout.attributes.to_vec(),
+            submeshes: Vec::new(),
+        })
+    }
+
+    /// Splits the mesh into `submeshes`, which have to lie within its indices.
+    pub fn with_submeshes(mut self, submeshes: Vec<Submesh>) -> Result<Self, AndError> {
+        if let Some(submesh) = submeshes.iter()
+            .find(|submesh| submesh.indices.start > submesh.indices.end || submesh.indices.end > self.index_count)
+        {
+            return Err(AndError::InvalidMesh(format!(
+                "submesh {:?} spans indices {:?}, outside of the {} there are",
+                submesh.name, submesh.indices, self.index_count,
+            )));
+        }
+
+        self.submeshes = submeshes;
+        Ok(self)
+    }
+
+    pub fn submeshes(&self) -> &[Submesh] {
+        &self.submeshes
+    }
+
+    pub fn layout(&self) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: self.array_stride,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &self.attributes,
+        }
+    }
+
+    pub fn bind<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        pass.set_vertex_buffer(0, self.vertices.slice(..));
+        pass.set_index_buffer(self.indices.slice(..), wgpu::IndexFormat::Uint32);
+    }
+
+    /// Draws every index of the buffers bound through [`Mesh::bind`], once per instance in `instances`.
+    pub fn draw(&self, pass: &mut wgpu::RenderPass, instances: Range<u32>) {
+        pass.draw_indexed(0..self.index_count, 0, instances);
+    }
+}
+
+/// Pipelines the mesh is drawn with, both built for its vertex layout.
+pub struct MeshPipelines {
+    pub opaque: wgpu::RenderPipeline,
+    /// Alpha blended, testing depth without writing it, so translucent draws don't hide what's behind them.
+    pub transparent: wgpu::RenderPipeline,
+    /// Opaque and white, reading each instance's transform from [`MeshDraws::instance_layout`] in vertex buffer 1.
+    pub instanced: wgpu::RenderPipeline,
+}
+
+/// One draw of the mesh, queued through [`crate::State::draw_mesh`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshDraw {
+    pub transform: Mat4,
+    /// Straight, not premultiplied, alpha; only blended for `transparent` draws.
+    pub color: [f32; 4],
+    /// Draw after every opaque draw, blended onto them without writing depth.
+    pub transparent: bool,
+}
+
+impl MeshDraw {
+    const UNTRANSFORMED: Self = Self { transform: Mat4::IDENTITY, color: [1.0; 4], transparent: false, };
+}
+
+impl Default for MeshDraw {
+    fn default() -> Self {
+        Self::UNTRANSFORMED
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct DrawUniform {
+    model: [[f32; 4]; 4],
+    color: [f32; 4],
+}
+
+/// This frame's mesh draws, each with its transform and color in its own slot of a uniform buffer, picked per draw
+/// with a dynamic offset, and instances, whose transforms go in a vertex buffer drawn from in a single call.
+pub struct MeshDraws {
+    draws: Vec<MeshDraw>,
+    /// Set when the draws were replaced as a whole, so that none at all means nothing's drawn.
+    replaced: bool,
+    buffer: Tracked<wgpu::Buffer>,
+    capacity: usize,
+    stride: wgpu::BufferAddress,
+    pub layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    instances: Vec<[[f32; 4]; 4]>,
+    instance_buffer: Tracked<wgpu::Buffer>,
+    instance_capacity: usize,
+}
+
+impl MeshDraws {
+    /// Shader locations of an instance transform's columns. Mesh layouts can't use them.
+    pub const INSTANCE_LOCATIONS: Range<u32> = 12..16;
+    const INSTANCE_ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        12 => Float32x4, 13 => Float32x4, 14 => Float32x4, 15 => Float32x4,
+    ];
+
+    pub fn instance_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::INSTANCE_ATTRIBUTES,
+        }
+    }
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let stride = upload::dynamic_stride::<DrawUniform>(device);
+        let buffer = Self::create_buffer(device, stride, 1);
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Mesh draws bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<DrawUniform>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let bind_group = Self::create_bind_group(device, &layout, &buffer);
+
+        Self {
+            draws: Vec::new(), replaced: false, buffer, capacity: 1, stride, layout, bind_group,
+            instances: Vec::new(),
+            instance_buffer: Self::create_instance_buffer(device, 1),
+            instance_capacity: 1,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, instances: usize) -> Tracked<wgpu::Buffer> {
+        resources::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some("Mesh instances"),
+            size: (instances * std::mem::size_of::<[[f32; 4]; 4]>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_buffer(device: &wgpu::Device, stride: wgpu::BufferAddress, draws: usize) -> Tracked<wgpu::Buffer> {
+        resources::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some("Mesh draws"),
+            size: stride * draws as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device, layout: &wgpu::BindGroupLayout, buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Mesh draws bind group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<DrawUniform>() as u64),
+                }),
+            }],
+        })
+    }
+
+    pub fn push(&mut self, draw: MeshDraw) {
+        self.draws.push(draw);
+    }
+
+    /// Replaces this frame's draws, drawing nothing if `draws` is empty.
+    pub fn replace(&mut self, draws: impl IntoIterator<Item = MeshDraw>) {
+        self.draws.clear();
+        self.draws.extend(draws);
+        self.replaced = true;
+    }
+
+    /// Replaces this frame's draws with an instance per transform in `transforms`, drawing nothing if it's empty.
+    pub fn replace_instanced(&mut self, transforms: impl IntoIterator<Item = Mat4>) {
+        self.replace([]);
+        self.instances.clear();
+        self.instances.extend(transforms.into_iter().map(|transform| transform.to_cols_array_2d()));
+    }
+
+    pub fn instance_count(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// This frame's draws, or a single default one if none were queued. Leaves the queue itself alone, so preparing
+    /// for an offscreen pass doesn't leave the default behind for the next frame.
+    fn queued(&self) -> &[MeshDraw] {
+        if self.draws.is_empty() && !self.replaced {
+            std::slice::from_ref(&MeshDraw::UNTRANSFORMED)
+        } else {
+            &self.draws
+        }
+    }
+
+    /// Uploads this frame's draws, or a single default one if none were queued, and instances, growing the buffers
+    /// if they don't fit.
+    pub fn prepare(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, uploader: &mut Uploader) {
+        if !self.instances.is_empty() {
+            if self.instances.len() > self.instance_capacity {
+                self.instance_capacity = self.instances.len().next_power_of_two();
+                self.instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+            }
+            uploader.write(device, encoder, &self.instance_buffer, 0, bytemuck::cast_slice(&self.instances));
+        }
+
+        let draws = self.queued().len();
+        if draws > self.capacity {
+            self.capacity = draws.next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.stride, self.capacity);
+            self.bind_group = Self::create_bind_group(device, &self.layout, &self.buffer);
+        }
+
+        for (i, draw) in self.queued().iter().enumerate() {
+            let uniform = DrawUniform { model: draw.transform.to_cols_array_2d(), color: draw.color, };
+            let offset = i as wgpu::BufferAddress * self.stride;
+            uploader.write(device, encoder, &self.buffer, offset, bytemuck::bytes_of(&uniform));
+        }
+    }
+
+    /// Draws every opaque draw in the order they were queued, then every instance, then every transparent draw on
+    /// top, back to front as seen from `eye` if `sort` is set. Group 1 is bound per draw; the view uniform has to be
+    /// bound already.
+    pub fn draw<'a>(
+        &'a self, pass: &mut wgpu::RenderPass<'a>, mesh: &'a Mesh, pipelines: &'a MeshPipelines, eye: Vec3, sort: bool,
+    ) {
+        let draw = |pass: &mut wgpu::RenderPass<'a>, i: usize| {
+            pass.set_bind_group(1, &self.bind_group, &[(i as wgpu::BufferAddress * self.stride) as u32]);
+            mesh.draw(pass, 0..1);
+        };
+
+        mesh.bind(pass);
+        pass.set_pipeline(&pipelines.opaque);
+        let draws = self.queued();
+        for (i, _) in draws.iter().enumerate().filter(|(_, draw)| !draw.transparent) {
+            draw(pass, i);
+        }
+
+        if !self.instances.is_empty() {
+            pass.set_pipeline(&pipelines.instanced);
+            // The instanced entry points don't read group 1, but the shared layout still needs it bound.
+            pass.set_bind_group(1, &self.bind_group, &[0]);
+            pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            mesh.draw(pass, 0..self.instances.len() as u32);
+        }
+
+        let mut transparent = draws.iter().enumerate().filter(|(_, draw)| draw.transparent).collect::<Vec<_>>();
+        if transparent.is_empty() { return };
+        if sort {
+            // By the distance to each draw's origin, which is as good as a single key per draw gets.
+            let distance = |draw: &MeshDraw| draw.transform.w_axis.truncate().distance_squared(eye);
+            transparent.sort_by(|(_, a), (_, b)| distance(b).total_cmp(&distance(a)));
+        }
+
+        pass.set_pipeline(&pipelines.transparent);
+        for (i, _) in transparent {
+            draw(pass, i);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.draws.clear();
+        self.instances.clear();
+        self.replaced = false;
+    }
+}
+
+/// Catches what wgpu would otherwise only report as a validation error once the pipeline or draw is made, or not
+/// at all, such as indices past the last vertex.
+fn validate(
+    limits: &wgpu::Limits, vertices: &[u8], layout: &wgpu::VertexBufferLayout, indices: &[u32],
+) -> Result<(), String> {
+    let stride = layout.array_stride;
+    if stride == 0 || !stride.is_multiple_of(wgpu::VERTEX_STRIDE_ALIGNMENT) {
+        return Err(format!("stride {stride} isn't a nonzero multiple of {}", wgpu::VERTEX_STRIDE_ALIGNMENT));
+    }
+    if stride > limits.max_vertex_buffer_array_stride as wgpu::BufferAddress {
+        return Err(format!("stride {stride} is above the device's limit of {}", limits.max_vertex_buffer_array_stride));
+    }
+    if layout.step_mode != wgpu::VertexStepMode::Vertex {
+        return Err("the layout has to step per vertex".into());
+    }
+
+    for attribute in layout.attributes {
+        let end = attribute.offset + attribute.format.size();
+        if end > stride {
+            let location = attribute.shader_location;
+            return Err(format!("attribute at location {location} ends at byte {end}, past the stride"));
+        }
+        if attribute.shader_location >= limits.max_vertex_attributes {
+            return Err(format!("location {} is above the device's limit", attribute.shader_location));
+        }
+        if MeshDraws::INSTANCE_LOCATIONS.contains(&attribute.shader_location) {
+            return Err(format!("location {} is reserved for instance transforms", attribute.shader_location));
+        }
+    }
+    match layout.attributes.iter().find(|attribute| attribute.shader_location == 0) {
+        Some(attribute) if attribute.format == wgpu::VertexFormat::Float32x3 => {},
+        Some(attribute) => return Err(format!("the position at location 0 is {:?}, not Float32x3", attribute.format)),
+        None => return Err("there's no position at location 0".into()),
+    }
+
+    if vertices.is_empty() || !(vertices.len() as wgpu::BufferAddress).is_multiple_of(stride) {
+        return Err(format!("{} bytes of vertices aren't a whole number of {stride} byte vertices", vertices.len()));
+    }
+    let vertex_count = vertices.len() as wgpu::BufferAddress / stride;
+    if let Some(index) = indices.iter().find(|&&index| index as wgpu::BufferAddress >= vertex_count) {
+        return Err(format!("index {index} is past the last of {vertex_count} vertices"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITION: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+
+    fn layout(stride: wgpu::BufferAddress, attributes: &[wgpu::VertexAttribute]) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout { array_stride: stride, step_mode: wgpu::VertexStepMode::Vertex, attributes, }
+    }
+
+    fn check(vertices: usize, layout: &wgpu::VertexBufferLayout, indices: &[u32]) -> Result<(), String> {
+        validate(&wgpu::Limits::default(), &vec![0; vertices], layout, indices)
+    }
+
+    #[test]
+    fn valid() {
+        assert_eq!(check(36, &layout(12, &POSITION), &[0, 1, 2]), Ok(()));
+        assert_eq!(check(36, &layout(12, &POSITION), &[]), Ok(()));
+        assert_eq!(check(bytemuck::bytes_of(&Vertex::default()).len(), &Vertex::layout(), &[0, 0, 0]), Ok(()));
+    }
+
+    #[test]
+    fn bad_strides() {
+        assert!(check(36, &layout(0, &POSITION), &[0]).is_err());
+        assert!(check(42, &layout(14, &POSITION), &[0]).is_err());
+        let too_wide = wgpu::Limits::default().max_vertex_buffer_array_stride as wgpu::BufferAddress + 4;
+        assert!(check(too_wide as usize, &layout(too_wide, &POSITION), &[0]).is_err());
+    }
+
+    #[test]
+    fn instance_stepping() {
+        let layout = wgpu::VertexBufferLayout { step_mode: wgpu::VertexStepMode::Instance, ..layout(12, &POSITION) };
+        assert!(check(36, &layout, &[0]).is_err());
+    }
+
+    #[test]
+    fn bad_attributes() {
+        // Past the stride, past the device's locations, reserved for instances, and a position that's missing or of
+        // the wrong format.
+        assert!(check(32, &layout(16, &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2]), &[0]).is_err());
+        assert!(check(32, &layout(16, &wgpu::vertex_attr_array![0 => Float32x3, 16 => Float32]), &[0]).is_err());
+        assert!(check(32, &layout(16, &wgpu::vertex_attr_array![0 => Float32x3, 12 => Float32]), &[0]).is_err());
+        assert!(check(36, &layout(12, &wgpu::vertex_attr_array![1 => Float32x3]), &[0]).is_err());
+        assert!(check(32, &layout(8, &wgpu::vertex_attr_array![0 => Float32x2]), &[0]).is_err());
+    }
+
+    #[test]
+    fn bad_vertex_bytes() {
+        assert!(check(0, &layout(12, &POSITION), &[]).is_err());
+        assert!(check(30, &layout(12, &POSITION), &[0]).is_err());
+    }
+
+    #[test]
+    fn index_past_the_last_vertex() {
+        assert!(check(36, &layout(12, &POSITION), &[0, 1, 3]).is_err());
+        assert!(check(36, &layout(12, &POSITION), &[u32::MAX]).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{
+    mesh::{
+        Submesh,
+        Vertex,
+    },
+    AndError,
+};
+use glam::Vec3;
+use std::path::{
+    Path, PathBuf,
+};
+
+/// A material from the MTL library an OBJ file refers to. Its diffuse color is already baked into the vertex colors;
+/// the texture is left for the caller to load, e.g. through [`crate::State::load_texture_async`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjMaterial {
+    pub name: String,
+    /// Diffuse color, with the material's dissolve as alpha.
+    pub diffuse: [f32; 4],
+    /// Relative to the working directory, resolved against the OBJ file's.
+    pub diffuse_texture: Option<PathBuf>,
+}
+
+/// An OBJ model flattened into one vertex and index buffer, with a [`Submesh`] per object or group in the file and
+/// the materials they refer to.
+#[derive(Debug, Clone, Default)]
+pub struct ObjModel {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub submeshes: Vec<Submesh>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+/// Parses an OBJ file and its MTL library into [`Vertex`]es, triangulating faces with more than three corners.
+/// Texture coordinates are flipped to wgpu's top-left origin, and models without normals get smooth ones. A missing
+/// or broken MTL library only logs a warning, leaving every vertex white.
+pub fn load_obj(path: impl AsRef<Path>) -> Result<ObjModel, AndError> {
+    let path = path.as_ref();
+    let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+    let materials = materials.unwrap_or_else(|e| {
+        log::warn!("Couldn't load the materials of {}: {e}", path.display());
+        Vec::new()
+    });
+
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let materials = materials.into_iter().map(|material| {
+        let [r, g, b] = material.diffuse.unwrap_or([1.0; 3]);
+        ObjMaterial {
+            name: material.name,
+            diffuse: [r, g, b, material.dissolve.unwrap_or(1.0)],
+            diffuse_texture: material.diffuse_texture.map(|texture| directory.join(texture)),
+        }
+    }).collect();
+
+    let mut obj = ObjModel { materials, ..Default::default() };
+    for tobj::Model { mesh, name, } in models {
+        let material = mesh.material_id.filter(|&i| i < obj.materials.len());
+        let tint = material.map_or([1.0; 4], |i| obj.materials[i].diffuse);
+
+        let base = obj.vertices.len();
+        obj.vertices.extend((0..mesh.positions.len() / 3).map(|i| {
+            let vec3 = |values: &[f32]| values.get(i * 3..i * 3 + 3).map(|v| [v[0], v[1], v[2]]);
+            let [r, g, b] = vec3(&mesh.vertex_color).unwrap_or([1.0; 3]);
+            Vertex {
+                position: vec3(&mesh.positions).expect("Within the positions"),
+                normal: vec3(&mesh.normals).unwrap_or_default(),
+                uv: mesh.texcoords.get(i * 2..i * 2 + 2).map_or([0.0; 2], |uv| [uv[0], 1.0 - uv[1]]),
+                color: [r * tint[0], g * tint[1], b * tint[2], tint[3]],
+            }
+        }));
+
+        let start = obj.indices.len() as u32;
+        obj.indices.extend(mesh.indices.iter().map(|&index| base as u32 + index));
+        if mesh.normals.is_empty() {
+            smooth_normals(&mut obj.vertices[base..], &mesh.indices);
+        }
+        obj.submeshes.push(Submesh { name, material, indices: start..obj.indices.len() as u32, });
+    }
+
+    if obj.indices.is_empty() {
+        return Err(AndError::InvalidMesh(format!("{} has no faces", path.display())));
+    }
+    Ok(obj)
+}
+
+/// Averages the normals of the triangles around each vertex, weighted by their area. Triangles with indices past
+/// the vertices are skipped here and rejected once the mesh is created.
+fn smooth_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vec3::ZERO; vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        if triangle.iter().any(|&index| index as usize >= vertices.len()) { continue };
+
+        let [a, b, c] = [0, 1, 2].map(|corner| Vec3::from(vertices[triangle[corner] as usize].position));
+        let normal = (b - a).cross(c - a);
+        for &index in triangle {
+            normals[index as usize] += normal;
+        }
+    }
+
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.normal = normal.normalize_or_zero().into();
+    }
+}
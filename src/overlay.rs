@@ -0,0 +1,60 @@
+use crate::{
+    shapes::{
+        Rect,
+        Shapes,
+    },
+    text::{
+        self,
+        DebugText,
+    },
+};
+
+/// Frame budget at 60 FPS, in milliseconds. The graph spans two of them.
+const BUDGET: f32 = 1000.0 / 60.0;
+/// In logical pixels, scaled by the window's scale factor.
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 4.0;
+const BAR_WIDTH: f32 = 2.0;
+const GRAPH_HEIGHT: f32 = 64.0;
+
+/// Adds a graph of `frame_times` in milliseconds to the top-left corner of the frame, one bar per frame, colored by
+/// whether it made the 60 or 30 FPS budget, with a line marking the former. Printed below are their average,
+/// `gpu_time` in milliseconds or "n/a" without one, and the adapter's name and backend.
+pub(crate) fn frame_time_graph(
+    shapes: &mut Shapes, text: &mut DebugText, frame_times: &[f32], gpu_time: Option<f32>,
+    adapter: &wgpu::AdapterInfo, scale: f32,
+) {
+    let average = frame_times.iter().sum::<f32>() / frame_times.len().max(1) as f32;
+    let gpu_time = gpu_time.map_or_else(|| "n/a".to_owned(), |time| format!("{time:.2} ms"));
+    let label = format!(
+        "{average:.1} ms, {:.0} FPS\nGPU {gpu_time}\n{} ({:?})",
+        1000.0 / average.max(f32::EPSILON), adapter.name, adapter.backend,
+    );
+    let columns = label.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+    let rows = label.lines().count();
+
+    let (margin, padding) = (MARGIN * scale, PADDING * scale);
+    let (bar_width, height, glyph) = (BAR_WIDTH * scale, GRAPH_HEIGHT * scale, 8.0 * scale);
+    let width = frame_times.len().max(1) as f32 * bar_width;
+    let panel_width = width.max(columns as f32 * glyph) + padding * 2.0;
+    let label_height = (rows - 1) as f32 * text::LINE_HEIGHT * scale + glyph;
+    let panel = Rect::new(margin, margin, panel_width, height + label_height + padding * 3.0);
+    shapes.rounded_rect(panel, padding, [0.0, 0.0, 0.0, 0.6], None);
+
+    let (left, bottom) = (margin + padding, margin + padding + height);
+    for (i, &time) in frame_times.iter().enumerate() {
+        let color = if time <= BUDGET {
+            [0.3, 0.9, 0.4, 0.9]
+        } else if time <= BUDGET * 2.0 {
+            [0.95, 0.8, 0.3, 0.9]
+        } else {
+            [0.95, 0.3, 0.3, 0.9]
+        };
+
+        let bar = (time / (BUDGET * 2.0)).clamp(0.0, 1.0) * height;
+        shapes.rounded_rect(Rect::new(left + i as f32 * bar_width, bottom - bar, bar_width, bar), 0.0, color, None);
+    }
+
+    shapes.rounded_rect(Rect::new(left, bottom - height * 0.5, width, scale.max(1.0)), 0.0, [1.0, 1.0, 1.0, 0.5], None);
+    text.text(&label, left, bottom + padding, scale, [1.0; 4]);
+}
@@ -0,0 +1,387 @@
+use crate::{
+    resources::{
+        self,
+        Tracked,
+    },
+    sprite::BlendMode,
+    upload::{
+        self,
+        Uploader,
+    },
+};
+use bytemuck::{
+    Pod, Zeroable,
+};
+use glam::{
+    Mat4, Vec3, Vec4,
+};
+use std::ops::Range;
+
+/// A particle to spawn, in world space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    pub position: Vec3,
+    /// In world units per second.
+    pub velocity: Vec3,
+    /// Seconds until it dies.
+    pub lifetime: f32,
+    /// Diameter in world units.
+    pub size: f32,
+    /// At birth, fading linearly into `end_color` over its lifetime. Straight, not premultiplied, alpha.
+    pub color: [f32; 4],
+    pub end_color: [f32; 4],
+}
+
+impl Default for Particle {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            lifetime: 1.0,
+            size: 0.1,
+            color: [1.0; 4],
+            end_color: [1.0, 1.0, 1.0, 0.0],
+        }
+    }
+}
+
+/// A fixed-capacity pool of particles simulated on the CPU, drawn through [`crate::State::draw_particles`] as soft
+/// discs facing the camera. Particles are kept in flat arrays for each attribute, and dead ones are swapped out for
+/// the last live one, so the live ones stay packed and their slots are reused by the next spawns.
+pub struct Particles {
+    /// Added to every particle's velocity, in world units per second squared.
+    pub gravity: Vec3,
+    /// Fraction of velocity lost per second.
+    pub drag: f32,
+    pub blend: BlendMode,
+    capacity: usize,
+    positions: Vec<Vec3>,
+    velocities: Vec<Vec3>,
+    ages: Vec<f32>,
+    lifetimes: Vec<f32>,
+    sizes: Vec<f32>,
+    colors: Vec<(Vec4, Vec4)>,
+}
+
+impl Particles {
+    /// Holds at most `capacity` live particles. Additively blended and without gravity or drag by default.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            gravity: Vec3::ZERO,
+            drag: 0.0,
+            blend: BlendMode::Additive,
+            capacity,
+            positions: Vec::with_capacity(capacity),
+            velocities: Vec::with_capacity(capacity),
+            ages: Vec::with_capacity(capacity),
+            lifetimes: Vec::with_capacity(capacity),
+            sizes: Vec::with_capacity(capacity),
+            colors: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns `false` without spawning anything if the pool is full. Particles that wouldn't live are ignored.
+    pub fn spawn(&mut self, particle: Particle) -> bool {
+        if self.len() >= self.capacity { return false };
+        if particle.lifetime <= 0.0 { return true };
+
+        self.positions.push(particle.position);
+        self.velocities.push(particle.velocity);
+        self.ages.push(0.0);
+        self.lifetimes.push(particle.lifetime);
+        self.sizes.push(particle.size);
+        self.colors.push((Vec4::from(particle.color), Vec4::from(particle.end_color)));
+        true
+    }
+
+    /// Spawns up to `count` particles made by `particle` from their index in this burst, e.g. to randomize them.
+    /// Stops once the pool is full, returning how many were spawned.
+    pub fn emit(&mut self, count: usize, mut particle: impl FnMut(usize) -> Particle) -> usize {
+        let count = count.min(self.capacity - self.len());
+        for i in 0..count {
+            self.spawn(particle(i));
+        }
+        count
+    }
+
+    /// Advances every particle by `dt` seconds, killing those past their lifetime.
+    pub fn update(&mut self, dt: f32) {
+        let damping = (1.0 - self.drag * dt).max(0.0);
+        let mut i = 0;
+        while i < self.len() {
+            self.ages[i] += dt;
+            if self.ages[i] >= self.lifetimes[i] {
+                self.kill(i);
+                continue;
+            }
+
+            self.velocities[i] = (self.velocities[i] + self.gravity * dt) * damping;
+            self.positions[i] += self.velocities[i] * dt;
+            i += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.velocities.clear();
+        self.ages.clear();
+        self.lifetimes.clear();
+        self.sizes.clear();
+        self.colors.clear();
+    }
+
+    fn kill(&mut self, i: usize) {
+        self.positions.swap_remove(i);
+        self.velocities.swap_remove(i);
+        self.ages.swap_remove(i);
+        self.lifetimes.swap_remove(i);
+        self.sizes.swap_remove(i);
+        self.colors.swap_remove(i);
+    }
+
+    fn instances(&self) -> impl Iterator<Item = ParticleInstance> + '_ {
+        (0..self.len()).map(|i| {
+            let (start, end) = self.colors[i];
+            ParticleInstance {
+                position: self.positions[i].into(),
+                size: self.sizes[i],
+                color: start.lerp(end, self.ages[i] / self.lifetimes[i]).into(),
+            }
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ParticleInstance {
+    position: [f32; 3],
+    size: f32,
+    color: [f32; 4],
+}
+
+impl ParticleInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+        0 => Float32x3, 1 => Float32, 2 => Float32x4,
+    ];
+
+    fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ParticleView {
+    view_proj: [[f32; 4]; 4],
+    eye: [f32; 3],
+    _pad: f32,
+    up: [f32; 3],
+    _pad2: f32,
+}
+
+/// Immediate-mode renderer for [`Particles`]. Every pool drawn during the frame is copied into one instance buffer
+/// and drawn with one instanced draw call per run of pools sharing a [`BlendMode`], from every view. Particles test
+/// against the scene's depth without writing it, and aren't sorted.
+pub struct ParticleBatch {
+    instances: Vec<ParticleInstance>,
+    runs: Vec<(BlendMode, Range<u32>)>,
+    buffer: Tracked<wgpu::Buffer>,
+    capacity: usize,
+    /// One view per view the scene is drawn from, `stride` bytes apart.
+    uniform: Tracked<wgpu::Buffer>,
+    views: usize,
+    stride: wgpu::BufferAddress,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipelines: [wgpu::RenderPipeline; BlendMode::ALL.len()],
+}
+
+impl ParticleBatch {
+    const INITIAL_CAPACITY: usize = 1024;
+
+    pub fn new(
+        device: &wgpu::Device, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, sample_count: u32,
+        reverse_z: bool,
+    ) -> Self {
+        let stride = upload::dynamic_stride::<ParticleView>(device);
+        let uniform = Self::create_uniform(device, stride, 1);
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particles bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<ParticleView>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let bind_group = Self::create_bind_group(device, &layout, &uniform);
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("particles.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particles pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipelines = BlendMode::ALL.map(|blend| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Particles pipeline ({blend:?})")),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[ParticleInstance::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(blend.state()),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: if reverse_z {
+                    wgpu::CompareFunction::GreaterEqual
+                } else {
+                    wgpu::CompareFunction::LessEqual
+                },
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+        }));
+
+        Self {
+            instances: Vec::new(),
+            runs: Vec::new(),
+            buffer: Self::create_buffer(device, Self::INITIAL_CAPACITY),
+            capacity: Self::INITIAL_CAPACITY,
+            views: 1,
+            uniform, stride, layout, bind_group, pipelines,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> Tracked<wgpu::Buffer> {
+        resources::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some("Particle instances"),
+            size: (capacity * std::mem::size_of::<ParticleInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_uniform(device: &wgpu::Device, stride: wgpu::BufferAddress, views: usize) -> Tracked<wgpu::Buffer> {
+        resources::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some("Particles uniform"),
+            size: stride * views as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device, layout: &wgpu::BindGroupLayout, uniform: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particles bind group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: uniform,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<ParticleView>() as u64),
+                }),
+            }],
+        })
+    }
+
+    /// Queues every live particle of `particles` as it is now.
+    pub fn push(&mut self, particles: &Particles) {
+        if particles.is_empty() { return };
+
+        let start = self.instances.len() as u32;
+        self.instances.extend(particles.instances());
+        let end = self.instances.len() as u32;
+        match self.runs.last_mut() {
+            Some((blend, range)) if *blend == particles.blend => range.end = end,
+            _ => self.runs.push((particles.blend, start..end)),
+        }
+    }
+
+    /// Uploads this frame's particles and the view-projection, eye and up vector of every view they're drawn from,
+    /// growing the buffers if they don't fit.
+    pub fn prepare(
+        &mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, uploader: &mut Uploader,
+        views: &[(Mat4, Vec3, Vec3)],
+    ) {
+        if self.instances.is_empty() { return };
+
+        if self.instances.len() > self.capacity {
+            self.capacity = self.instances.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+        if views.len() > self.views {
+            self.views = views.len().next_power_of_two();
+            self.uniform = Self::create_uniform(device, self.stride, self.views);
+            self.bind_group = Self::create_bind_group(device, &self.layout, &self.uniform);
+        }
+
+        for (i, &(view_proj, eye, up)) in views.iter().enumerate() {
+            let view = ParticleView {
+                view_proj: view_proj.to_cols_array_2d(),
+                eye: eye.into(),
+                _pad: 0.0,
+                up: up.into(),
+                _pad2: 0.0,
+            };
+            let offset = i as wgpu::BufferAddress * self.stride;
+            uploader.write(device, encoder, &self.uniform, offset, bytemuck::bytes_of(&view));
+        }
+        uploader.write(device, encoder, &self.buffer, 0, bytemuck::cast_slice(&self.instances));
+    }
+
+    /// Draws the particles as seen from the `view`th view passed to [`ParticleBatch::prepare`].
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, view: usize) {
+        if self.instances.is_empty() { return };
+
+        pass.set_bind_group(0, &self.bind_group, &[(view as wgpu::BufferAddress * self.stride) as u32]);
+        pass.set_vertex_buffer(0, self.buffer.slice(..));
+        for (blend, range) in &self.runs {
+            pass.set_pipeline(&self.pipelines[*blend as usize]);
+            pass.draw(0..4, range.clone());
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.instances.clear();
+        self.runs.clear();
+    }
+}
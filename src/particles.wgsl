@@ -0,0 +1,52 @@
+struct View {
+    view_proj: mat4x4<f32>,
+    eye: vec3<f32>,
+    up: vec3<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> view: View;
+
+struct Instance {
+    @location(0) position: vec3<f32>,
+    @location(1) size: f32,
+    @location(2) color: vec4<f32>,
+};
+
+struct VertOut {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) local: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, in: Instance) -> VertOut {
+    // Triangle strip over the quad's corners, from -1 to 1 on both axes.
+    let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u)) * 2.0 - vec2<f32>(1.0);
+
+    // Faces the eye, rolled to keep the camera's up vector up, falling back to X when looking straight along it.
+    let facing = normalize(view.eye - in.position);
+    var right = cross(view.up, facing);
+    if (dot(right, right) < 1e-8) {
+        right = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    right = normalize(right);
+    let up = cross(facing, right);
+    let world = in.position + (right * corner.x + up * corner.y) * (in.size * 0.5);
+
+    var out: VertOut;
+    out.clip_position = view.view_proj * vec4<f32>(world, 1.0);
+    out.local = corner;
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertOut) -> @location(0) vec4<f32> {
+    // A soft disc, fully opaque up to half its radius.
+    let falloff = 1.0 - smoothstep(0.5, 1.0, length(in.local));
+    if (falloff <= 0.0) {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, in.color.a * falloff);
+}
@@ -0,0 +1,55 @@
+use crate::RenderCallback;
+
+struct Pass {
+    name: String,
+    load: Option<wgpu::LoadOp<wgpu::Color>>,
+    draw: RenderCallback,
+}
+
+/// Render passes registered by name through [`crate::State::register_pass`], drawn into the scene's attachments
+/// after the scene pass in the order they were first registered.
+#[derive(Default)]
+pub struct Passes {
+    passes: Vec<Pass>,
+}
+
+impl Passes {
+    pub fn contains(&self, name: &str) -> bool {
+        self.passes.iter().any(|pass| pass.name == name)
+    }
+
+    /// In drawing order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.passes.iter().map(|pass| pass.name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Replaces a pass by the same name where it is, or goes after every other one.
+    pub(crate) fn insert(&mut self, name: String, load: Option<wgpu::LoadOp<wgpu::Color>>, draw: RenderCallback) {
+        let pass = Pass { name, load, draw, };
+        match self.passes.iter_mut().find(|existing| existing.name == pass.name) {
+            Some(existing) => *existing = pass,
+            None => self.passes.push(pass),
+        }
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        let len = self.passes.len();
+        self.passes.retain(|pass| pass.name != name);
+        self.passes.len() != len
+    }
+
+    /// Every pass's name, requested load op and callback, in drawing order.
+    pub(crate) fn iter_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (&str, Option<wgpu::LoadOp<wgpu::Color>>, &mut RenderCallback)> {
+        self.passes.iter_mut().map(|pass| (pass.name.as_str(), pass.load, &mut pass.draw))
+    }
+}
@@ -0,0 +1,66 @@
+use crate::uniforms::Uniforms;
+use std::collections::HashMap;
+
+/// What registered pipelines render into, handed to their builders.
+pub struct PipelineTarget<'a> {
+    pub device: &'a wgpu::Device,
+    /// Color format of the scene, see [`crate::State::scene_format`].
+    pub color_format: wgpu::TextureFormat,
+    /// Depth format of the scene; pipelines used in the scene pass need a depth-stencil state of this format.
+    pub depth_format: wgpu::TextureFormat,
+    /// Sample count of the scene pass, which pipelines used in it need in their multisample state.
+    pub sample_count: u32,
+    /// Uniforms set through [`crate::State::set_uniform`], for their layouts.
+    pub uniforms: &'a Uniforms,
+}
+
+pub type PipelineBuilder = Box<dyn Fn(&PipelineTarget) -> wgpu::RenderPipeline>;
+
+/// Render pipelines registered by name through [`crate::State::register_pipeline`]. Builders are kept around, so
+/// every pipeline can be rebuilt whenever the scene's attachments change format or the device is recreated.
+#[derive(Default)]
+pub struct Pipelines {
+    builders: HashMap<String, PipelineBuilder>,
+    pipelines: HashMap<String, wgpu::RenderPipeline>,
+}
+
+impl Pipelines {
+    pub fn get(&self, name: &str) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.pipelines.contains_key(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.pipelines.keys().map(String::as_str)
+    }
+
+    pub(crate) fn insert(&mut self, name: String, builder: PipelineBuilder, target: &PipelineTarget) {
+        self.pipelines.insert(name.clone(), builder(target));
+        self.builders.insert(name, builder);
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        self.builders.remove(name);
+        self.pipelines.remove(name).is_some()
+    }
+
+    pub(crate) fn rebuild(&mut self, target: &PipelineTarget) {
+        for (name, builder) in &self.builders {
+            self.pipelines.insert(name.clone(), builder(target));
+        }
+    }
+}
+
+/// Handed to [`crate::Config::on_render`] along with the scene pass. Everything in here outlives the pass, so it
+/// can be bound in it.
+pub struct RenderContext<'a> {
+    pub pipelines: &'a Pipelines,
+    /// Bound with [`Uniforms::bind`], for pipelines built against their layouts.
+    pub uniforms: &'a Uniforms,
+    /// Size of the target the pass renders into.
+    pub width: u32,
+    pub height: u32,
+}
@@ -0,0 +1,161 @@
+use wgpu::util::DeviceExt as _;
+use std::{
+    fmt,
+    ops::Deref,
+    sync::atomic::{
+        AtomicU64, AtomicUsize, Ordering,
+    },
+};
+
+struct Counter {
+    count: AtomicUsize,
+    bytes: AtomicU64,
+}
+
+impl Counter {
+    const fn new() -> Self {
+        Self { count: AtomicUsize::new(0), bytes: AtomicU64::new(0), }
+    }
+
+    fn add(&self, bytes: u64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn sub(&self, bytes: u64) {
+        self.count.fetch_sub(1, Ordering::Relaxed);
+        self.bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+static TEXTURES: Counter = Counter::new();
+static BUFFERS: Counter = Counter::new();
+
+/// Textures and buffers the crate currently holds, with their approximate sizes. wgpu doesn't expose this, so it's
+/// counted as they're created through this module and dropped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub textures: usize,
+    pub texture_bytes: u64,
+    pub buffers: usize,
+    pub buffer_bytes: u64,
+}
+
+impl fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "{} textures ({} KiB), {} buffers ({} KiB)",
+            self.textures, self.texture_bytes / 1024, self.buffers, self.buffer_bytes / 1024,
+        )
+    }
+}
+
+pub fn usage() -> ResourceUsage {
+    ResourceUsage {
+        textures: TEXTURES.count.load(Ordering::Relaxed),
+        texture_bytes: TEXTURES.bytes.load(Ordering::Relaxed),
+        buffers: BUFFERS.count.load(Ordering::Relaxed),
+        buffer_bytes: BUFFERS.bytes.load(Ordering::Relaxed),
+    }
+}
+
+/// A texture or buffer counted in [`ResourceUsage`] for as long as it's alive.
+pub struct Tracked<T> {
+    resource: T,
+    counter: &'static Counter,
+    bytes: u64,
+}
+
+impl<T> Tracked<T> {
+    fn new(resource: T, counter: &'static Counter, bytes: u64) -> Self {
+        counter.add(bytes);
+        Self { resource, counter, bytes, }
+    }
+}
+
+impl<T> Deref for Tracked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.resource
+    }
+}
+
+impl<T> Drop for Tracked<T> {
+    fn drop(&mut self) {
+        self.counter.sub(self.bytes);
+    }
+}
+
+/// Size of every mip level and sample of the texture, ignoring padding the driver might add.
+fn texture_bytes(desc: &wgpu::TextureDescriptor) -> u64 {
+    let info = desc.format.describe();
+    let (block_width, block_height) = (info.block_dimensions.0 as u32, info.block_dimensions.1 as u32);
+
+    let level_bytes = |mip: u32| {
+        let width = (desc.size.width >> mip).max(1).div_ceil(block_width);
+        let height = (desc.size.height >> mip).max(1).div_ceil(block_height);
+        width as u64 * height as u64 * desc.size.depth_or_array_layers as u64 * info.block_size as u64
+    };
+    (0..desc.mip_level_count).map(level_bytes).sum::<u64>() * desc.sample_count as u64
+}
+
+pub fn create_texture(device: &wgpu::Device, desc: &wgpu::TextureDescriptor) -> Tracked<wgpu::Texture> {
+    Tracked::new(device.create_texture(desc), &TEXTURES, texture_bytes(desc))
+}
+
+pub fn create_texture_with_data(
+    device: &wgpu::Device, queue: &wgpu::Queue, desc: &wgpu::TextureDescriptor, data: &[u8],
+) -> Tracked<wgpu::Texture> {
+    Tracked::new(device.create_texture_with_data(queue, desc, data), &TEXTURES, texture_bytes(desc))
+}
+
+pub fn create_buffer(device: &wgpu::Device, desc: &wgpu::BufferDescriptor) -> Tracked<wgpu::Buffer> {
+    Tracked::new(device.create_buffer(desc), &BUFFERS, desc.size)
+}
+
+pub fn create_buffer_init(device: &wgpu::Device, desc: &wgpu::util::BufferInitDescriptor) -> Tracked<wgpu::Buffer> {
+    Tracked::new(device.create_buffer_init(desc), &BUFFERS, desc.contents.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replacing_keeps_the_count() {
+        static COUNTER: Counter = Counter::new();
+        let counted = || (COUNTER.count.load(Ordering::Relaxed), COUNTER.bytes.load(Ordering::Relaxed));
+
+        let mut resource = Tracked::new((), &COUNTER, 64);
+        assert_eq!(counted(), (1, 64));
+        for bytes in [16, 256, 64] {
+            // Like a resize does it, the replacement is created before the old one is dropped.
+            resource = Tracked::new((), &COUNTER, bytes);
+            assert_eq!(counted(), (1, bytes));
+        }
+
+        drop(resource);
+        assert_eq!(counted(), (0, 0));
+    }
+
+    #[test]
+    fn texture_sizes() {
+        let desc = |format, width, height, mip_level_count, sample_count| wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1, },
+            mip_level_count,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        };
+
+        assert_eq!(texture_bytes(&desc(wgpu::TextureFormat::Rgba8Unorm, 256, 128, 1, 1)), 256 * 128 * 4);
+        assert_eq!(texture_bytes(&desc(wgpu::TextureFormat::Depth32Float, 256, 128, 1, 4)), 256 * 128 * 4 * 4);
+        // 16x8, 8x4, 4x2, 2x1 and 1x1, down to a single pixel.
+        assert_eq!(texture_bytes(&desc(wgpu::TextureFormat::R8Unorm, 16, 8, 5, 1)), 128 + 32 + 8 + 2 + 1);
+        // Eight bytes per 4x4 block, with partial blocks rounded up.
+        assert_eq!(texture_bytes(&desc(wgpu::TextureFormat::Bc1RgbaUnorm, 10, 4, 1, 1)), 3 * 8);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::AndError;
+use std::collections::HashMap;
+
+/// How texture coordinates outside `0..1` are resolved along each axis. `ClampToBorder`, which clamps to transparent
+/// black, needs [`wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER`]; the rest work everywhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AddressModes {
+    pub u: wgpu::AddressMode,
+    pub v: wgpu::AddressMode,
+    pub w: wgpu::AddressMode,
+}
+
+impl Default for AddressModes {
+    /// Clamps to the edge, so a texture stretched over a quad doesn't bleed in texels from its opposite edge.
+    fn default() -> Self {
+        Self::all(wgpu::AddressMode::ClampToEdge)
+    }
+}
+
+impl AddressModes {
+    /// The same mode on every axis, e.g. `Repeat` for tiling.
+    pub fn all(mode: wgpu::AddressMode) -> Self {
+        Self { u: mode, v: mode, w: mode, }
+    }
+
+    fn clamps_to_border(self) -> bool {
+        [self.u, self.v, self.w].contains(&wgpu::AddressMode::ClampToBorder)
+    }
+
+    /// Fails with [`AndError::UnsupportedAddressMode`] if any axis clamps to the border without `features` allowing
+    /// it.
+    pub(crate) fn check(self, features: wgpu::Features) -> Result<Self, AndError> {
+        if self.clamps_to_border() && !features.contains(wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER) {
+            return Err(AndError::UnsupportedAddressMode(wgpu::AddressMode::ClampToBorder));
+        }
+
+        Ok(self)
+    }
+}
+
+/// Linear samplers, created the first time each combination of address modes is asked for. There are only so many
+/// combinations, so they're never evicted.
+pub struct Samplers {
+    samplers: HashMap<AddressModes, wgpu::Sampler>,
+}
+
+impl Samplers {
+    pub fn new() -> Self {
+        Self { samplers: HashMap::new(), }
+    }
+
+    pub fn get(&mut self, device: &wgpu::Device, modes: AddressModes) -> &wgpu::Sampler {
+        self.samplers.entry(modes).or_insert_with(|| device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Texture sampler"),
+            address_mode_u: modes.u,
+            address_mode_v: modes.v,
+            address_mode_w: modes.w,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            border_color: modes.clamps_to_border().then_some(wgpu::SamplerBorderColor::TransparentBlack),
+            ..Default::default()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_to_border_needs_its_feature() {
+        use wgpu::AddressMode::*;
+        let border = AddressModes { u: Repeat, v: ClampToBorder, w: ClampToEdge, };
+        assert!(border.check(wgpu::Features::empty()).is_err());
+        assert_eq!(border.check(wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER).ok(), Some(border));
+        for mode in [ClampToEdge, Repeat, MirrorRepeat] {
+            assert_eq!(AddressModes::all(mode).check(wgpu::Features::empty()).ok(), Some(AddressModes::all(mode)));
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{
+    AndError, Config, FullscreenMode, PresentModePolicy,
+};
+use serde::{
+    Deserialize, Serialize,
+};
+use winit::dpi::PhysicalSize;
+use std::{
+    fs, io,
+    path::Path,
+};
+
+/// The subset of [`Config`] end users may want to change without recompiling, as stored in a settings file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Window size in physical pixels; the OS decides if absent.
+    pub resolution: Option<[u32; 2]>,
+    /// Borderless, unless [`Config::fullscreen`] already asks for a specific fullscreen mode.
+    pub fullscreen: bool,
+    pub vsync: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self { resolution: None, fullscreen: false, vsync: true, }
+    }
+}
+
+impl Settings {
+    /// Reads settings from the TOML file at `path`, writing the defaults there first if it doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self, AndError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(toml::from_str(&text)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let settings = Self::default();
+                settings.save(path)?;
+                Ok(settings)
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), AndError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            resolution: config.size.map(|size| [size.width, size.height]),
+            fullscreen: config.fullscreen != FullscreenMode::Windowed,
+            vsync: match config.present_mode_policy {
+                PresentModePolicy::Auto => !matches!(
+                    config.present_mode,
+                    wgpu::PresentMode::AutoNoVsync | wgpu::PresentMode::Immediate | wgpu::PresentMode::Mailbox,
+                ),
+                PresentModePolicy::LowLatency => false,
+                PresentModePolicy::Smooth | PresentModePolicy::PowerSaving => true,
+            },
+        }
+    }
+
+    /// Overwrites the fields of `config` these settings cover, leaving the rest alone.
+    pub fn apply(&self, config: &mut Config) {
+        config.size = self.resolution.map(|[width, height]| PhysicalSize::new(width, height));
+        config.fullscreen = match (self.fullscreen, config.fullscreen) {
+            (false, _) => FullscreenMode::Windowed,
+            (true, FullscreenMode::Windowed) => FullscreenMode::Borderless,
+            (true, mode) => mode,
+        };
+        // Present mode policies other than `LowLatency` already wait for vertical blank.
+        if self.vsync {
+            config.present_mode = wgpu::PresentMode::AutoVsync;
+            if config.present_mode_policy == PresentModePolicy::LowLatency {
+                config.present_mode_policy = PresentModePolicy::default();
+            }
+        } else {
+            config.present_mode = wgpu::PresentMode::AutoNoVsync;
+            config.present_mode_policy = PresentModePolicy::Auto;
+        }
+    }
+}
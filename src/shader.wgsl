@@ -16,3 +16,104 @@ fn vs_main(@builtin(vertex_index) index: u32) -> VertOut {
 fn fs_main(in: VertOut) -> @location(0) vec4<f32> {
     return vec4<f32>(0.3, 0.2, 0.1, 1.0);
 }
+
+struct Globals {
+    view_proj: mat4x4<f32>,
+};
+
+struct Draw {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> globals: Globals;
+@group(1) @binding(0)
+var<uniform> draw: Draw;
+
+@vertex
+fn vs_mesh(@location(0) position: vec3<f32>) -> VertOut {
+    var out: VertOut;
+    out.clip_position = globals.view_proj * draw.model * vec4<f32>(position, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_mesh(in: VertOut) -> @location(0) vec4<f32> {
+    return draw.color;
+}
+
+struct Instance {
+    @location(12) model_0: vec4<f32>,
+    @location(13) model_1: vec4<f32>,
+    @location(14) model_2: vec4<f32>,
+    @location(15) model_3: vec4<f32>,
+};
+
+fn instance_model(instance: Instance) -> mat4x4<f32> {
+    return mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+}
+
+@vertex
+fn vs_mesh_instanced(@location(0) position: vec3<f32>, instance: Instance) -> VertOut {
+    var out: VertOut;
+    out.clip_position = globals.view_proj * instance_model(instance) * vec4<f32>(position, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_mesh_instanced(in: VertOut) -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0);
+}
+
+struct Light {
+    direction: vec3<f32>,
+    color: vec3<f32>,
+    ambient: vec3<f32>,
+};
+
+@group(2) @binding(0)
+var<uniform> light: Light;
+
+struct LitOut {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+};
+
+// For meshes with a normal at location 1. Correct for uniformly scaled transforms only, which keep normals
+// perpendicular to their surfaces.
+@vertex
+fn vs_mesh_lit(@location(0) position: vec3<f32>, @location(1) normal: vec3<f32>) -> LitOut {
+    var out: LitOut;
+    out.clip_position = globals.view_proj * draw.model * vec4<f32>(position, 1.0);
+    out.normal = (draw.model * vec4<f32>(normal, 0.0)).xyz;
+    return out;
+}
+
+fn shade(normal: vec3<f32>) -> vec3<f32> {
+    // Degenerate normals only get the ambient term.
+    let normal = normal / max(length(normal), 1e-6);
+    let diffuse = max(dot(normal, -light.direction), 0.0);
+    return light.ambient + light.color * diffuse;
+}
+
+@fragment
+fn fs_mesh_lit(in: LitOut) -> @location(0) vec4<f32> {
+    return vec4<f32>(draw.color.rgb * shade(in.normal), draw.color.a);
+}
+
+@vertex
+fn vs_mesh_instanced_lit(
+    @location(0) position: vec3<f32>, @location(1) normal: vec3<f32>, instance: Instance,
+) -> LitOut {
+    let model = instance_model(instance);
+    var out: LitOut;
+    out.clip_position = globals.view_proj * model * vec4<f32>(position, 1.0);
+    out.normal = (model * vec4<f32>(normal, 0.0)).xyz;
+    return out;
+}
+
+@fragment
+fn fs_mesh_instanced_lit(in: LitOut) -> @location(0) vec4<f32> {
+    return vec4<f32>(shade(in.normal), 1.0);
+}
@@ -0,0 +1,359 @@
+use crate::{
+    resources::{
+        self,
+        Tracked,
+    },
+    target,
+    upload::Uploader,
+};
+use bytemuck::{
+    Pod, Zeroable,
+};
+use glam::Vec2;
+use std::ops::Range;
+
+/// An axis-aligned rectangle in physical pixels, from the top-left of the frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub position: Vec2,
+    pub size: Vec2,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self { position: Vec2::new(x, y), size: Vec2::new(width, height), }
+    }
+
+    /// The overlap of both, empty if there's none.
+    pub fn intersect(self, other: Rect) -> Rect {
+        let min = self.position.max(other.position);
+        let max = (self.position + self.size).min(other.position + other.size);
+        Rect { position: min, size: (max - min).max(Vec2::ZERO), }
+    }
+}
+
+/// A scissor rect as fractions of the frame it was given in, so it can be mapped onto a target of any resolution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scissor {
+    min: Vec2,
+    max: Vec2,
+}
+
+impl Scissor {
+    pub fn new(rect: Rect, width: u32, height: u32) -> Self {
+        let frame = Vec2::new(width as f32, height as f32);
+        Self { min: rect.position / frame, max: (rect.position + rect.size) / frame, }
+    }
+
+    /// Sets the scissor rect to this scissor on a `width` by `height` target, clamped to its bounds as wgpu requires.
+    /// Returns `false` without setting anything if nothing would be left to draw.
+    pub fn apply(self, pass: &mut wgpu::RenderPass, width: u32, height: u32) -> bool {
+        let target = Vec2::new(width as f32, height as f32);
+        let min = (self.min * target).round().clamp(Vec2::ZERO, target);
+        let max = (self.max * target).round().clamp(Vec2::ZERO, target);
+        let size = max - min;
+        if size.x < 1.0 || size.y < 1.0 { return false };
+
+        pass.set_scissor_rect(min.x as u32, min.y as u32, size.x as u32, size.y as u32);
+        true
+    }
+}
+
+/// Draws consecutive runs of instances that share a scissor, restoring the full-target scissor afterwards.
+pub fn draw_scissored<'a, T>(
+    pass: &mut wgpu::RenderPass<'a>, runs: impl IntoIterator<Item = (Option<Scissor>, T)>, width: u32, height: u32,
+    mut draw: impl FnMut(&mut wgpu::RenderPass<'a>, T),
+) {
+    let mut current = None;
+    for (scissor, run) in runs {
+        if scissor != current {
+            match scissor {
+                Some(scissor) if !scissor.apply(pass, width, height) => continue,
+                Some(_) => {},
+                None => pass.set_scissor_rect(0, 0, width, height),
+            }
+            current = scissor;
+        }
+
+        draw(pass, run);
+    }
+
+    if current.is_some() {
+        pass.set_scissor_rect(0, 0, width, height);
+    }
+}
+
+/// A crosshair of four arms around the center of the frame, in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrosshairSettings {
+    /// Length from the end of one arm to the end of the opposite one.
+    pub size: f32,
+    pub thickness: f32,
+    /// Empty space between the center and each arm.
+    pub gap: f32,
+    /// Straight, not premultiplied, alpha.
+    pub color: [f32; 4],
+}
+
+impl Default for CrosshairSettings {
+    fn default() -> Self {
+        Self { size: 16.0, thickness: 2.0, gap: 0.0, color: [1.0; 4], }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ShapeInstance {
+    position: [f32; 2],
+    size: [f32; 2],
+    color: [f32; 4],
+    radius: f32,
+}
+
+impl ShapeInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        0 => Float32x2, 1 => Float32x2, 2 => Float32x4, 3 => Float32,
+    ];
+
+    fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Immediate-mode renderer for anti-aliased rounded rectangles and circles, drawn as signed distance fields over
+/// quads. All of a frame's shapes go out in one draw call per run of shapes sharing a scissor rect.
+///
+/// With a stencil attachment, shapes can also be drawn as masks: they write the stencil reference where they cover
+/// the frame, without touching the color, and go out before the rest of the scene so it can test against them.
+pub struct Shapes {
+    instances: Vec<ShapeInstance>,
+    masks: Vec<ShapeInstance>,
+    runs: Vec<(Option<Rect>, Range<u32>)>,
+    scissors: Vec<(Option<Scissor>, Range<u32>)>,
+    buffer: Tracked<wgpu::Buffer>,
+    capacity: usize,
+    uniform: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    mask_pipeline: Option<wgpu::RenderPipeline>,
+}
+
+impl Shapes {
+    const INITIAL_CAPACITY: usize = 256;
+
+    pub fn new(
+        device: &wgpu::Device, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, sample_count: u32,
+    ) -> Self {
+        let uniform = resources::create_buffer_init(device, &wgpu::util::BufferInitDescriptor {
+            label: Some("Shapes screen uniform"),
+            contents: bytemuck::bytes_of(&[1.0f32; 4]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shapes bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shapes bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shapes.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shapes pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point, write_mask, stencil| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[ShapeInstance::layout()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: depth_format,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil,
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+                multiview: None,
+            })
+        };
+
+        let pipeline = create_pipeline("Shapes pipeline", "fs_main", wgpu::ColorWrites::ALL, Default::default());
+        // Masks need somewhere to write to; without a stencil aspect they're dropped instead.
+        let mask_pipeline = (depth_format == target::depth_format(true)).then(|| {
+            let face = wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::Always,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op: wgpu::StencilOperation::Replace,
+            };
+            create_pipeline("Shape masks pipeline", "fs_mask", wgpu::ColorWrites::empty(), wgpu::StencilState {
+                front: face, back: face, read_mask: !0, write_mask: !0,
+            })
+        });
+
+        Self {
+            instances: Vec::new(),
+            masks: Vec::new(),
+            runs: Vec::new(),
+            scissors: Vec::new(),
+            buffer: Self::create_buffer(device, Self::INITIAL_CAPACITY),
+            capacity: Self::INITIAL_CAPACITY,
+            uniform, bind_group, pipeline, mask_pipeline,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> Tracked<wgpu::Buffer> {
+        resources::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some("Shape instances"),
+            size: (capacity * std::mem::size_of::<ShapeInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// `radius` is clamped to half the shorter side, which turns a square into a circle. Drawn only within
+    /// `scissor` if set.
+    pub fn rounded_rect(&mut self, rect: Rect, radius: f32, color: [f32; 4], scissor: Option<Rect>) {
+        let i = self.instances.len() as u32;
+        match self.runs.last_mut() {
+            Some((run_scissor, range)) if *run_scissor == scissor => range.end = i + 1,
+            _ => self.runs.push((scissor, i..i + 1)),
+        }
+
+        self.instances.push(ShapeInstance {
+            position: rect.position.into(),
+            size: rect.size.into(),
+            color,
+            radius: radius.max(0.0),
+        });
+    }
+
+    /// Adds a crosshair centered on `center`. The arms don't overlap, so translucent colors blend evenly.
+    pub fn crosshair(&mut self, center: Vec2, settings: CrosshairSettings) {
+        let CrosshairSettings { size, thickness, gap, color, } = settings;
+        let (half, half_thickness) = (size * 0.5, thickness * 0.5);
+        // Where the horizontal arms meet, the vertical ones stop short of them.
+        let vertical_gap = gap.max(half_thickness);
+
+        let mut arm = |min: Vec2, max: Vec2| {
+            if max.x > min.x && max.y > min.y {
+                self.rounded_rect(Rect { position: center + min, size: max - min, }, 0.0, color, None);
+            }
+        };
+        arm(Vec2::new(-half, -half_thickness), Vec2::new(-gap, half_thickness));
+        arm(Vec2::new(gap, -half_thickness), Vec2::new(half, half_thickness));
+        arm(Vec2::new(-half_thickness, -half), Vec2::new(half_thickness, -vertical_gap));
+        arm(Vec2::new(-half_thickness, vertical_gap), Vec2::new(half_thickness, half));
+    }
+
+    /// Like [`Shapes::rounded_rect`], but writes the stencil reference instead of a color, anti-aliasing aside. Does
+    /// nothing without a stencil attachment.
+    pub fn mask_rounded_rect(&mut self, rect: Rect, radius: f32) {
+        if self.mask_pipeline.is_none() { return };
+        self.masks.push(ShapeInstance {
+            position: rect.position.into(),
+            size: rect.size.into(),
+            color: [0.0; 4],
+            radius: radius.max(0.0),
+        });
+    }
+
+    /// Uploads this frame's shapes for a `width` by `height` frame, growing the buffer if they don't fit. Masks go
+    /// first in the buffer, followed by the shapes.
+    pub fn prepare(
+        &mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, uploader: &mut Uploader,
+        width: u32, height: u32,
+    ) {
+        let len = self.masks.len() + self.instances.len();
+        if len == 0 { return };
+
+        if len > self.capacity {
+            self.capacity = len.next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+
+        self.scissors.clear();
+        self.scissors.extend(self.runs.iter().map(|(rect, range)| {
+            (rect.map(|rect| Scissor::new(rect, width, height)), range.clone())
+        }));
+
+        let screen = [width as f32, height as f32, 0.0, 0.0];
+        uploader.write(device, encoder, &self.uniform, 0, bytemuck::bytes_of(&screen));
+        if !self.masks.is_empty() {
+            uploader.write(device, encoder, &self.buffer, 0, bytemuck::cast_slice(&self.masks));
+        }
+        if !self.instances.is_empty() {
+            let offset = (self.masks.len() * std::mem::size_of::<ShapeInstance>()) as wgpu::BufferAddress;
+            uploader.write(device, encoder, &self.buffer, offset, bytemuck::cast_slice(&self.instances));
+        }
+    }
+
+    /// Writes this frame's masks into the stencil. Meant to go before anything testing against them.
+    pub fn draw_masks<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        let Some(pipeline) = &self.mask_pipeline else { return };
+        if self.masks.is_empty() { return };
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.buffer.slice(..));
+        pass.draw(0..4, 0..self.masks.len() as u32);
+    }
+
+    /// Draws into a `width` by `height` target, which scissor rects are mapped onto.
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, width: u32, height: u32) {
+        if self.instances.is_empty() { return };
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.buffer.slice(..));
+        let start = self.masks.len() as u32;
+        draw_scissored(pass, self.scissors.iter().cloned(), width, height, |pass, range| {
+            pass.draw(0..4, range.start + start..range.end + start)
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.instances.clear();
+        self.runs.clear();
+        self.scissors.clear();
+        self.masks.clear();
+    }
+}
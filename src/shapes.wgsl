@@ -0,0 +1,59 @@
+struct Screen {
+    size: vec2<f32>,
+    _pad: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+
+struct Instance {
+    @location(0) position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) radius: f32,
+};
+
+struct VertOut {
+    @builtin(position) clip_position: vec4<f32>,
+    // Offset from the shape's center, in pixels.
+    @location(0) local: vec2<f32>,
+    @location(1) @interpolate(flat) half_size: vec2<f32>,
+    @location(2) @interpolate(flat) radius: f32,
+    @location(3) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, in: Instance) -> VertOut {
+    // Grown by a pixel on each side to leave room for the anti-aliased edge.
+    let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+    let pixel = in.position - vec2<f32>(1.0) + corner * (in.size + vec2<f32>(2.0));
+
+    var out: VertOut;
+    out.clip_position = vec4<f32>(pixel / screen.size * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.local = pixel - (in.position + in.size * 0.5);
+    out.half_size = in.size * 0.5;
+    out.radius = min(in.radius, min(out.half_size.x, out.half_size.y));
+    out.color = in.color;
+    return out;
+}
+
+// Signed distance to the rounded rectangle's edge, negative inside.
+fn edge_distance(in: VertOut) -> f32 {
+    let q = abs(in.local) - in.half_size + vec2<f32>(in.radius);
+    return length(max(q, vec2<f32>(0.0))) + min(max(q.x, q.y), 0.0) - in.radius;
+}
+
+@fragment
+fn fs_main(in: VertOut) -> @location(0) vec4<f32> {
+    let coverage = clamp(0.5 - edge_distance(in), 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
+
+// The stencil has no coverage to blend with, so masks are cut at the edge itself.
+@fragment
+fn fs_mask(in: VertOut) -> @location(0) vec4<f32> {
+    if (edge_distance(in) > 0.0) {
+        discard;
+    }
+    return vec4<f32>(0.0);
+}
@@ -0,0 +1,437 @@
+use crate::{
+    resources::{
+        self,
+        Tracked,
+    },
+    loader::TextureHandle,
+    shapes::{
+        self,
+        Rect, Scissor,
+    },
+    upload::Uploader,
+};
+use bytemuck::{
+    Pod, Zeroable,
+};
+use glam::Vec2;
+use std::ops::Range;
+
+/// Order sprites are drawn in, mirroring established 2D APIs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteSortMode {
+    /// In the order they were submitted.
+    #[default]
+    Deferred,
+    /// Grouped by texture, for the fewest bind group switches. Overlapping sprites may draw in the wrong order.
+    Texture,
+    /// By ascending depth.
+    FrontToBack,
+    /// By descending depth, as needed for overlapping translucent sprites.
+    BackToFront,
+}
+
+/// How a sprite is blended onto what's behind it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BlendMode {
+    /// Regular alpha blending.
+    #[default]
+    Alpha,
+    /// Alpha blending of colors already multiplied by their alpha, such as textures loaded through
+    /// [`crate::State::load_texture_premultiplied`]. The tint then has to be premultiplied too, so that fading a
+    /// sprite out scales its color along with its alpha.
+    Premultiplied,
+    /// Adds the color, weighted by alpha, e.g. for glows and fire.
+    Additive,
+    /// Multiplies what's behind by the color, e.g. for shadows and tinting. Alpha is ignored.
+    Multiply,
+    /// Overwrites what's behind, alpha included.
+    Opaque,
+}
+
+impl BlendMode {
+    pub(crate) const ALL: [Self; 5] = [Self::Alpha, Self::Premultiplied, Self::Additive, Self::Multiply, Self::Opaque];
+
+    /// Color blending of this mode. All but `Opaque` leave the destination alpha alone, apart from `Alpha` and
+    /// `Premultiplied` which composite it as usual.
+    pub fn state(self) -> wgpu::BlendState {
+        let keep_alpha = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        match self {
+            Self::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            Self::Premultiplied => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            Self::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: keep_alpha,
+            },
+            Self::Multiply => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Dst,
+                    dst_factor: wgpu::BlendFactor::Zero,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: keep_alpha,
+            },
+            Self::Opaque => wgpu::BlendState::REPLACE,
+        }
+    }
+}
+
+/// A textured, tinted quad in screen space, drawn on top of the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprite {
+    pub texture: TextureHandle,
+    /// Top-left corner in physical pixels, from the top-left of the frame.
+    pub position: Vec2,
+    /// Size in physical pixels.
+    pub size: Vec2,
+    /// Only used for sorting; see [`SpriteSortMode`].
+    pub depth: f32,
+    pub color: [f32; 4],
+    pub blend: BlendMode,
+}
+
+impl Sprite {
+    pub fn new(texture: TextureHandle, position: Vec2, size: Vec2) -> Self {
+        Self { texture, position, size, depth: 0.0, color: [1.0; 4], blend: BlendMode::default(), }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct SpriteInstance {
+    position: [f32; 2],
+    size: [f32; 2],
+    color: [f32; 4],
+}
+
+impl SpriteInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+        0 => Float32x2, 1 => Float32x2, 2 => Float32x4,
+    ];
+
+    fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Sprites drawn in one draw call.
+struct Batch {
+    texture: TextureHandle,
+    blend: BlendMode,
+    range: Range<u32>,
+}
+
+/// Immediate-mode sprite renderer. Sprites are collected throughout the frame, sorted, then drawn with one instanced
+/// draw call per run of sprites sharing a texture, blend mode and scissor rect, up to [`SpriteBatch::max_batch`]
+/// sprites each.
+/// There's a pipeline per [`BlendMode`], all built up front.
+pub struct SpriteBatch {
+    pub sort_mode: SpriteSortMode,
+    pub max_batch: usize,
+    sprites: Vec<(Sprite, Option<Rect>)>,
+    batches: Vec<(Option<Scissor>, Batch)>,
+    buffer: Tracked<wgpu::Buffer>,
+    capacity: usize,
+    uniform: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    pipelines: [wgpu::RenderPipeline; BlendMode::ALL.len()],
+}
+
+impl SpriteBatch {
+    const INITIAL_CAPACITY: usize = 256;
+    pub const DEFAULT_MAX_BATCH: usize = 4096;
+
+    pub fn new(
+        device: &wgpu::Device, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, sample_count: u32,
+        texture_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let uniform = resources::create_buffer_init(device, &wgpu::util::BufferInitDescriptor {
+            label: Some("Sprite screen uniform"),
+            contents: bytemuck::bytes_of(&[1.0f32; 4]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sprite bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("sprite.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sprite pipeline layout"),
+            bind_group_layouts: &[&layout, texture_layout],
+            push_constant_ranges: &[],
+        });
+        let pipelines = BlendMode::ALL.map(|blend| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Sprite pipeline ({blend:?})")),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[SpriteInstance::layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(blend.state()),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+        }));
+
+        Self {
+            sort_mode: SpriteSortMode::default(),
+            max_batch: Self::DEFAULT_MAX_BATCH,
+            sprites: Vec::new(),
+            batches: Vec::new(),
+            buffer: Self::create_buffer(device, Self::INITIAL_CAPACITY),
+            capacity: Self::INITIAL_CAPACITY,
+            uniform, bind_group, pipelines,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> Tracked<wgpu::Buffer> {
+        resources::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some("Sprite instances"),
+            size: (capacity * std::mem::size_of::<SpriteInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Rebuilds the pipelines for new target formats or sample counts, keeping the batching settings.
+    pub fn set_format(
+        &mut self, device: &wgpu::Device, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat,
+        sample_count: u32, texture_layout: &wgpu::BindGroupLayout,
+    ) {
+        *self = Self {
+            sort_mode: self.sort_mode,
+            max_batch: self.max_batch,
+            ..Self::new(device, format, depth_format, sample_count, texture_layout)
+        };
+    }
+
+    /// Queues a sprite, drawn only within `scissor` if set.
+    pub fn push(&mut self, sprite: Sprite, scissor: Option<Rect>) {
+        self.sprites.push((sprite, scissor));
+    }
+
+    /// Textures this frame's sprites refer to, possibly repeated.
+    pub fn textures(&self) -> impl Iterator<Item = TextureHandle> + '_ {
+        self.sprites.iter().map(|(sprite, _)| sprite.texture)
+    }
+
+    /// Sorts this frame's sprites, splits them into batches and uploads them for a `width` by `height` frame.
+    /// Sprites whose texture isn't `loaded` yet are left out.
+    pub fn prepare(
+        &mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, uploader: &mut Uploader,
+        width: u32, height: u32, loaded: impl Fn(TextureHandle) -> bool,
+    ) {
+        self.batches.clear();
+        self.sprites.retain(|(sprite, _)| loaded(sprite.texture));
+        if self.sprites.is_empty() { return };
+
+        sort(&mut self.sprites, self.sort_mode);
+        let instances = split(&self.sprites, &mut self.batches, self.max_batch, width, height);
+
+        if instances.len() > self.capacity {
+            self.capacity = instances.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+
+        let screen = [width as f32, height as f32, 0.0, 0.0];
+        uploader.write(device, encoder, &self.uniform, 0, bytemuck::bytes_of(&screen));
+        uploader.write(device, encoder, &self.buffer, 0, bytemuck::cast_slice(&instances));
+    }
+
+    /// Number of draw calls [`SpriteBatch::draw`] issues this frame.
+    pub fn batch_count(&self) -> usize {
+        self.batches.len()
+    }
+
+    /// Draws into a `width` by `height` target, which scissor rects are mapped onto.
+    pub fn draw<'a>(
+        &'a self, pass: &mut wgpu::RenderPass<'a>, width: u32, height: u32,
+        bind_group: impl Fn(TextureHandle) -> Option<&'a wgpu::BindGroup>,
+    ) {
+        if self.batches.is_empty() { return };
+
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.buffer.slice(..));
+        let mut bound = None;
+        let batches = self.batches.iter().map(|(scissor, batch)| (*scissor, batch));
+        shapes::draw_scissored(pass, batches, width, height, |pass, batch| {
+            let Some(texture_bind_group) = bind_group(batch.texture) else { return };
+
+            if bound != Some(batch.blend) {
+                pass.set_pipeline(&self.pipelines[batch.blend as usize]);
+                bound = Some(batch.blend);
+            }
+            pass.set_bind_group(1, texture_bind_group, &[]);
+            pass.draw(0..4, batch.range.clone());
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.sprites.clear();
+    }
+}
+
+fn sort(sprites: &mut [(Sprite, Option<Rect>)], mode: SpriteSortMode) {
+    // All of these are stable, so sprites comparing equal keep their submission order.
+    match mode {
+        SpriteSortMode::Deferred => {},
+        SpriteSortMode::Texture => sprites.sort_by_key(|(sprite, _)| (sprite.blend, sprite.texture)),
+        SpriteSortMode::FrontToBack => sprites.sort_by(|(a, _), (b, _)| a.depth.total_cmp(&b.depth)),
+        SpriteSortMode::BackToFront => sprites.sort_by(|(a, _), (b, _)| b.depth.total_cmp(&a.depth)),
+    }
+}
+
+/// Appends a batch to `batches` per run of consecutive sprites sharing a texture, blend mode and scissor rect, of at
+/// most `max_batch` sprites, and returns the sprites' instances in the same order.
+fn split(
+    sprites: &[(Sprite, Option<Rect>)], batches: &mut Vec<(Option<Scissor>, Batch)>, max_batch: usize,
+    width: u32, height: u32,
+) -> Vec<SpriteInstance> {
+    let max_batch = max_batch.max(1) as u32;
+    let mut instances = Vec::with_capacity(sprites.len());
+    for (i, (sprite, scissor)) in sprites.iter().enumerate() {
+        let i = i as u32;
+        let scissor = scissor.map(|rect| Scissor::new(rect, width, height));
+        match batches.last_mut() {
+            Some((batch_scissor, batch))
+                if *batch_scissor == scissor && batch.texture == sprite.texture && batch.blend == sprite.blend &&
+                    batch.range.end - batch.range.start < max_batch =>
+            {
+                batch.range.end = i + 1;
+            },
+            _ => batches.push((scissor, Batch { texture: sprite.texture, blend: sprite.blend, range: i..i + 1, })),
+        }
+
+        instances.push(SpriteInstance {
+            position: sprite.position.into(),
+            size: sprite.size.into(),
+            color: sprite.color,
+        });
+    }
+
+    instances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader;
+
+    fn sprite(texture: TextureHandle, depth: f32, blend: BlendMode) -> (Sprite, Option<Rect>) {
+        (Sprite { depth, blend, ..Sprite::new(texture, Vec2::ZERO, Vec2::ONE) }, None)
+    }
+
+    fn batches(sprites: &[(Sprite, Option<Rect>)], max_batch: usize) -> Vec<(TextureHandle, Range<u32>)> {
+        let mut batches = Vec::new();
+        let instances = split(sprites, &mut batches, max_batch, 100, 100);
+        assert_eq!(instances.len(), sprites.len());
+        batches.into_iter().map(|(_, batch)| (batch.texture, batch.range)).collect()
+    }
+
+    #[test]
+    fn empty() {
+        assert!(batches(&[], 8).is_empty());
+    }
+
+    #[test]
+    fn splits_on_texture_changes() {
+        let (a, b) = (loader::next_handle(), loader::next_handle());
+        let sprites = [a, a, b, a].map(|texture| sprite(texture, 0.0, BlendMode::Alpha));
+        assert_eq!(batches(&sprites, 8), [(a, 0..2), (b, 2..3), (a, 3..4)]);
+    }
+
+    #[test]
+    fn splits_on_blend_and_scissor_changes() {
+        let texture = loader::next_handle();
+        let mut sprites = [BlendMode::Alpha, BlendMode::Additive, BlendMode::Additive, BlendMode::Additive]
+            .map(|blend| sprite(texture, 0.0, blend));
+        sprites[3].1 = Some(Rect::new(0.0, 0.0, 10.0, 10.0));
+        assert_eq!(batches(&sprites, 8), [(texture, 0..1), (texture, 1..3), (texture, 3..4)]);
+    }
+
+    #[test]
+    fn splits_at_max_batch() {
+        let texture = loader::next_handle();
+        let sprites = [(); 5].map(|()| sprite(texture, 0.0, BlendMode::Alpha));
+        assert_eq!(batches(&sprites, 2), [(texture, 0..2), (texture, 2..4), (texture, 4..5)]);
+        // Zero is taken as one rather than never fitting anything.
+        assert_eq!(batches(&sprites[..2], 0), [(texture, 0..1), (texture, 1..2)]);
+    }
+
+    #[test]
+    fn sorts_by_texture_stably() {
+        let (a, b) = (loader::next_handle(), loader::next_handle());
+        let mut sprites = [(b, 0.0), (a, 1.0), (b, 2.0), (a, 3.0)].map(|(texture, depth)| {
+            sprite(texture, depth, BlendMode::Alpha)
+        });
+        sort(&mut sprites, SpriteSortMode::Texture);
+        let order = sprites.map(|(sprite, _)| (sprite.texture, sprite.depth));
+        assert_eq!(order, [(a, 1.0), (a, 3.0), (b, 0.0), (b, 2.0)]);
+        assert_eq!(batches(&sprites, 8), [(a, 0..2), (b, 2..4)]);
+    }
+
+    #[test]
+    fn sorts_by_depth() {
+        let texture = loader::next_handle();
+        let depths = |sprites: &[(Sprite, Option<Rect>)]| {
+            sprites.iter().map(|(sprite, _)| sprite.depth).collect::<Vec<_>>()
+        };
+        let mut sprites = [2.0, -1.0, 0.5].map(|depth| sprite(texture, depth, BlendMode::Alpha));
+
+        sort(&mut sprites, SpriteSortMode::Deferred);
+        assert_eq!(depths(&sprites), [2.0, -1.0, 0.5]);
+        sort(&mut sprites, SpriteSortMode::FrontToBack);
+        assert_eq!(depths(&sprites), [-1.0, 0.5, 2.0]);
+        sort(&mut sprites, SpriteSortMode::BackToFront);
+        assert_eq!(depths(&sprites), [2.0, 0.5, -1.0]);
+    }
+}
@@ -0,0 +1,80 @@
+/// Work the last frame recorded and submitted, e.g. to spot passes or submissions that could be merged. Counts
+/// only what the crate records itself; passes recorded in callbacks such as [`crate::Config::on_pre_render`] aren't
+/// seen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    pub command_buffers: u32,
+    pub render_passes: u32,
+}
+
+/// The last so many frame times, oldest first. Every time is written twice, half the buffer apart, so the retained
+/// window is always one contiguous slice no matter where it wraps around.
+pub struct FrameTimes {
+    times: Vec<f32>,
+    capacity: usize,
+    next: usize,
+    len: usize,
+}
+
+impl FrameTimes {
+    pub fn new(capacity: usize) -> Self {
+        Self { times: vec![0.0; capacity * 2], capacity, next: 0, len: 0, }
+    }
+
+    pub fn push(&mut self, time: f32) {
+        if self.capacity == 0 { return };
+
+        self.times[self.next] = time;
+        self.times[self.next + self.capacity] = time;
+        self.next = (self.next + 1) % self.capacity;
+        self.len = (self.len + 1).min(self.capacity);
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        let start = self.next + self.capacity - self.len;
+        &self.times[start..start + self.len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert!(FrameTimes::new(4).as_slice().is_empty());
+    }
+
+    #[test]
+    fn partially_filled() {
+        let mut times = FrameTimes::new(4);
+        times.push(1.0);
+        times.push(2.0);
+        assert_eq!(times.as_slice(), [1.0, 2.0]);
+    }
+
+    #[test]
+    fn wraps_oldest_first() {
+        let mut times = FrameTimes::new(3);
+        for time in 1..=7 {
+            times.push(time as f32);
+        }
+        assert_eq!(times.as_slice(), [5.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn exactly_full() {
+        let mut times = FrameTimes::new(3);
+        for time in 1..=3 {
+            times.push(time as f32);
+        }
+        assert_eq!(times.as_slice(), [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn zero_capacity_keeps_nothing() {
+        let mut times = FrameTimes::new(0);
+        times.push(1.0);
+        assert!(times.as_slice().is_empty());
+    }
+}
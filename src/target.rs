@@ -0,0 +1,396 @@
+use crate::{
+    resources::{
+        self,
+        Tracked,
+    },
+    shapes::Rect,
+};
+use bytemuck::{
+    Pod, Zeroable,
+};
+use std::cell::Cell;
+
+/// A filterable 2D texture at binding 0 and its sampler at binding 1, visible to fragment shaders.
+pub fn texture_layout(device: &wgpu::Device, label: &str) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(label),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true, },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Operator compressing a linear HDR frame into displayable range while presenting it. Anything other than
+/// [`Tonemap::None`] renders the scene into an `Rgba16Float` target.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemap {
+    #[default]
+    None,
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct BlitParams {
+    tonemap: u32,
+    encode_srgb: u32,
+    premultiplied: u32,
+    gamma: f32,
+    brightness: f32,
+    _pad: [u32; 3],
+}
+
+/// Draws a [`FrameTarget`] onto another texture with a fullscreen triangle, tonemapping and sRGB-encoding it on the
+/// way if the target is linear. Gamma and brightness are adjusted last, after tonemapping.
+pub struct Blit {
+    pub layout: wgpu::BindGroupLayout,
+    pub sampler: wgpu::Sampler,
+    pub pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    params: Tracked<wgpu::Buffer>,
+    params_bind_group: wgpu::BindGroup,
+    values: BlitParams,
+    /// What's left around the viewport is cleared to, see-through on a surface that shows alpha.
+    clear: wgpu::Color,
+}
+
+impl Blit {
+    /// `encode_srgb` is for linear frames presented to a surface format that doesn't encode to sRGB by itself.
+    /// Frames for a `PreMultiplied` `alpha_mode` are unpremultiplied around tonemapping and encoding, which only
+    /// make sense on straight color.
+    pub fn new(
+        device: &wgpu::Device, format: wgpu::TextureFormat, tonemap: Tonemap, encode_srgb: bool,
+        alpha_mode: wgpu::CompositeAlphaMode,
+    ) -> Self {
+        let premultiplied = alpha_mode == wgpu::CompositeAlphaMode::PreMultiplied;
+        // `Auto` is what opaque windows ask for, and may well resolve to a mode that shows alpha.
+        let clear = match alpha_mode {
+            wgpu::CompositeAlphaMode::Auto | wgpu::CompositeAlphaMode::Opaque => wgpu::Color::BLACK,
+            _ => wgpu::Color::TRANSPARENT,
+        };
+        let layout = texture_layout(device, "Blit bind group layout");
+        // Linear, for frames rendered at a different resolution than they're presented at.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Blit sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let values = BlitParams {
+            tonemap: tonemap as u32,
+            encode_srgb: encode_srgb as u32,
+            premultiplied: premultiplied as u32,
+            gamma: 1.0,
+            brightness: 1.0,
+            _pad: [0; 3],
+        };
+        let params = resources::create_buffer_init(device, &wgpu::util::BufferInitDescriptor {
+            label: Some("Blit params"),
+            contents: bytemuck::bytes_of(&values),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Blit params bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Blit params bind group"),
+            layout: &params_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("blit.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Blit pipeline layout"),
+            bind_group_layouts: &[&layout, &params_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(device, &pipeline_layout, &shader, format);
+
+        Self { layout, sampler, pipeline, pipeline_layout, shader, params, params_bind_group, values, clear, }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device, pipeline_layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Blit pipeline"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    /// Rebuilds the pipeline for another target format, keeping the bind group layouts and thereby every
+    /// [`FrameTarget`]'s bind group valid.
+    pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+        self.pipeline = Self::create_pipeline(device, &self.pipeline_layout, &self.shader, format);
+    }
+
+    pub fn set_tonemap(&mut self, queue: &wgpu::Queue, tonemap: Tonemap, encode_srgb: bool) {
+        self.values.tonemap = tonemap as u32;
+        self.values.encode_srgb = encode_srgb as u32;
+        queue.write_buffer(&self.params, 0, bytemuck::bytes_of(&self.values));
+    }
+
+    /// Raises the color to `1 / gamma` after scaling it by `brightness`, both 1 for no change.
+    pub fn set_adjustment(&mut self, queue: &wgpu::Queue, gamma: f32, brightness: f32) {
+        self.values.gamma = gamma;
+        self.values.brightness = brightness;
+        queue.write_buffer(&self.params, 0, bytemuck::bytes_of(&self.values));
+    }
+
+    /// Stretches `source` over `viewport` of `view`, in physical pixels, leaving the rest black, or transparent if
+    /// the surface shows alpha.
+    pub fn draw(
+        &self, encoder: &mut wgpu::CommandEncoder, source: &wgpu::BindGroup, view: &wgpu::TextureView, viewport: Rect,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Blit pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        pass.set_viewport(viewport.position.x, viewport.position.y, viewport.size.x, viewport.size.y, 0.0, 1.0);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, source, &[]);
+        pass.set_bind_group(1, &self.params_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+/// Stencil state of the scene pipeline, and what the stencil attachment is cleared to each frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StencilSettings {
+    pub clear: u32,
+    /// Value [`StencilSettings::compare`] tests against and `Replace` writes.
+    pub reference: u32,
+    pub compare: wgpu::CompareFunction,
+    /// Applied where the stencil test fails...
+    pub fail_op: wgpu::StencilOperation,
+    /// ...where it passes but the depth test fails...
+    pub depth_fail_op: wgpu::StencilOperation,
+    /// ...and where both pass.
+    pub pass_op: wgpu::StencilOperation,
+    pub read_mask: u32,
+    pub write_mask: u32,
+}
+
+impl Default for StencilSettings {
+    /// Always passes and never writes, so nothing changes until the ops do.
+    fn default() -> Self {
+        Self {
+            clear: 0,
+            reference: 0,
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Keep,
+            read_mask: !0,
+            write_mask: !0,
+        }
+    }
+}
+
+impl StencilSettings {
+    pub fn state(&self) -> wgpu::StencilState {
+        let face = wgpu::StencilFaceState {
+            compare: self.compare,
+            fail_op: self.fail_op,
+            depth_fail_op: self.depth_fail_op,
+            pass_op: self.pass_op,
+        };
+        wgpu::StencilState { front: face, back: face, read_mask: self.read_mask, write_mask: self.write_mask, }
+    }
+}
+
+/// Depth format of the scene's attachments. Stencil comes with less well-defined depth precision, since
+/// `Depth24Plus` may or may not be a float format, so it's only picked when asked for.
+pub fn depth_format(stencil: bool) -> wgpu::TextureFormat {
+    if stencil {
+        wgpu::TextureFormat::Depth24PlusStencil8
+    } else {
+        wgpu::TextureFormat::Depth32Float
+    }
+}
+
+/// A depth attachment of a fixed size. The texture is kept next to its view, so it's counted in the resource usage
+/// for exactly as long as the view keeps it alive.
+pub struct DepthTarget {
+    pub view: wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub width: u32,
+    pub height: u32,
+    rendered: Cell<bool>,
+    _texture: Tracked<wgpu::Texture>,
+}
+
+impl DepthTarget {
+    pub fn new(
+        device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32, width: u32, height: u32,
+    ) -> Self {
+        let texture = depth_texture(device, format, sample_count, width, height);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { view, format, sample_count, width, height, rendered: Cell::new(false), _texture: texture, }
+    }
+
+    /// Whether this is the first pass to render into this target, which every pass after it won't be.
+    pub fn first_pass(&self) -> bool {
+        !self.rendered.replace(true)
+    }
+}
+
+fn depth_texture(
+    device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32, width: u32, height: u32,
+) -> Tracked<wgpu::Texture> {
+    resources::create_texture(device, &wgpu::TextureDescriptor {
+        label: Some("Depth"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1, },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    })
+}
+
+/// Multisampled color attachment the scene is drawn into and resolved from, when MSAA is on. Only ever a render
+/// attachment, so its samples never leave the pass they're resolved in.
+pub struct MsaaTarget {
+    pub view: wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub width: u32,
+    pub height: u32,
+    _texture: Tracked<wgpu::Texture>,
+}
+
+impl MsaaTarget {
+    /// `None` for a `sample_count` of 1, which resolves nothing and draws into the color target directly.
+    pub fn new(
+        device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32, width: u32, height: u32,
+    ) -> Option<Self> {
+        if sample_count <= 1 { return None };
+
+        let texture = resources::create_texture(device, &wgpu::TextureDescriptor {
+            label: Some("MSAA color"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1, },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Some(Self { view, format, sample_count, width, height, _texture: texture, })
+    }
+}
+
+/// Offscreen texture the scene is rendered into before being blitted to the surface. Outlives the surface texture
+/// it's presented through, so the last frame stays readable.
+pub struct FrameTarget {
+    pub texture: Tracked<wgpu::Texture>,
+    pub view: wgpu::TextureView,
+    pub width: u32,
+    pub height: u32,
+    pub depth: DepthTarget,
+    /// What the scene pass draws into instead of `texture` with MSAA on, resolved into it at the end of the pass.
+    pub msaa: Option<MsaaTarget>,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl FrameTarget {
+    pub fn new(
+        device: &wgpu::Device, blit: &Blit,
+        format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, sample_count: u32,
+        width: u32, height: u32,
+    ) -> Self {
+        let texture = resources::create_texture(device, &wgpu::TextureDescriptor {
+            label: Some("Frame target"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1, },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage:
+                wgpu::TextureUsages::RENDER_ATTACHMENT |
+                wgpu::TextureUsages::TEXTURE_BINDING |
+                wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth = DepthTarget::new(device, depth_format, sample_count, width, height);
+        let msaa = MsaaTarget::new(device, format, sample_count, width, height);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Frame target bind group"),
+            layout: &blit.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&blit.sampler),
+                },
+            ],
+        });
+
+        Self { texture, view, width, height, depth, msaa, bind_group, }
+    }
+}
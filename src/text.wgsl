@@ -0,0 +1,49 @@
+struct Screen {
+    size: vec2<f32>,
+    _pad: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+@group(0) @binding(1)
+var atlas: texture_2d<f32>;
+@group(0) @binding(2)
+var atlas_sampler: sampler;
+
+struct Instance {
+    @location(0) position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) glyph: u32,
+    @location(3) color: vec4<f32>,
+};
+
+struct VertOut {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, in: Instance) -> VertOut {
+    // Triangle strip over the quad's corners: top-left, top-right, bottom-left, bottom-right.
+    let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+    let pixel = in.position + corner * in.size;
+
+    // 16 glyphs to a row of the atlas, each 8 texels wide inside a cell padded to 10 so filtering doesn't bleed.
+    let cell = vec2<f32>(f32(in.glyph % 16u), f32(in.glyph / 16u));
+    let texel = cell * 10.0 + vec2<f32>(1.0) + corner * 8.0;
+
+    var out: VertOut;
+    out.clip_position = vec4<f32>(pixel / screen.size * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = texel / vec2<f32>(textureDimensions(atlas));
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertOut) -> @location(0) vec4<f32> {
+    // The filtered bitmap, sharpened back into an edge about a pixel wide at any scale.
+    let coverage = textureSample(atlas, atlas_sampler, in.uv).r;
+    let alpha = clamp((coverage - 0.5) / max(fwidth(coverage), 1e-4) + 0.5, 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * alpha);
+}
@@ -0,0 +1,181 @@
+use crate::{
+    error::AndError,
+    resources::{
+        self,
+        Tracked,
+    },
+};
+use std::{
+    collections::BTreeMap,
+    num::NonZeroU64,
+};
+
+struct Binding {
+    /// One per frame in flight, all created holding `data`.
+    buffers: Vec<Tracked<wgpu::Buffer>>,
+    /// Which of `buffers` were last written `data`; the others are written it once their frame comes around.
+    current: Vec<bool>,
+    /// The last value written, padded to the buffer's size, so the buffer can be recreated on another device.
+    data: Vec<u8>,
+}
+
+impl Binding {
+    fn new(device: &wgpu::Device, frames: usize, data: Vec<u8>) -> Self {
+        let buffers = (0..frames).map(|_| resources::create_buffer_init(device, &wgpu::util::BufferInitDescriptor {
+            label: Some("User uniform buffer"),
+            contents: &data,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        })).collect();
+        Self { buffers, current: vec![true; frames], data, }
+    }
+
+    fn write(&mut self, queue: &wgpu::Queue, frame: usize) {
+        queue.write_buffer(&self.buffers[frame], 0, &self.data);
+        self.current[frame] = true;
+    }
+}
+
+struct Group {
+    bindings: BTreeMap<u32, Binding>,
+    layout: wgpu::BindGroupLayout,
+    /// One per frame in flight, each pointing at that frame's buffers.
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl Group {
+    fn new(device: &wgpu::Device, frames: usize, bindings: BTreeMap<u32, Binding>) -> Self {
+        let entries = bindings.iter().map(|(&binding, uniform)| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(uniform.data.len() as u64),
+            },
+            count: None,
+        }).collect::<Vec<_>>();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("User uniform bind group layout"),
+            entries: &entries,
+        });
+
+        let bind_groups = (0..frames).map(|frame| {
+            let entries = bindings.iter().map(|(&binding, uniform)| wgpu::BindGroupEntry {
+                binding,
+                resource: uniform.buffers[frame].as_entire_binding(),
+            }).collect::<Vec<_>>();
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("User uniform bind group"),
+                layout: &layout,
+                entries: &entries,
+            })
+        }).collect();
+
+        Self { bindings, layout, bind_groups, }
+    }
+}
+
+/// Uniform buffers set through [`crate::State::set_uniform`], with one bind group per group index holding every
+/// binding set in it. Pipelines using them are built against [`Uniforms::layout`].
+///
+/// Every buffer is kept once per frame in flight, as per [`crate::Config::frames_in_flight`], and frames cycle
+/// through them. Values are written to the current frame's copy, so a write never has to wait on the GPU reading
+/// one of the frames before it.
+pub struct Uniforms {
+    groups: BTreeMap<u32, Group>,
+    frames: usize,
+    frame: usize,
+}
+
+impl Uniforms {
+    /// WGSL rounds the size of uniform buffer bindings up to a multiple of this, so buffers are padded to it.
+    pub const ALIGNMENT: u64 = 16;
+
+    /// Keeps `frames` copies of every buffer, at least one.
+    pub(crate) fn new(frames: u32) -> Self {
+        Self { groups: BTreeMap::new(), frames: frames.max(1) as usize, frame: 0, }
+    }
+
+    /// Layout of the group, once anything was set in it. Changes whenever a binding is added or changes size.
+    pub fn layout(&self, group: u32) -> Option<&wgpu::BindGroupLayout> {
+        self.groups.get(&group).map(|group| &group.layout)
+    }
+
+    /// The current frame's bind group of the group.
+    pub fn bind_group(&self, group: u32) -> Option<&wgpu::BindGroup> {
+        self.groups.get(&group).map(|group| &group.bind_groups[self.frame])
+    }
+
+    /// Indices of every group with anything set in it, in ascending order.
+    pub fn groups(&self) -> impl Iterator<Item = u32> + '_ {
+        self.groups.keys().copied()
+    }
+
+    /// Sets every group's bind group for the current frame at its own index.
+    pub fn bind<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        for (&index, group) in &self.groups {
+            pass.set_bind_group(index, &group.bind_groups[self.frame], &[]);
+        }
+    }
+
+    /// Writes `data` to the current frame's copy of the binding, creating its buffers if it's new or changed size.
+    /// Returns whether the group's layout changed.
+    pub(crate) fn set(
+        &mut self, device: &wgpu::Device, queue: &wgpu::Queue, group: u32, binding: u32, data: &[u8],
+    ) -> Result<bool, AndError> {
+        let limits = device.limits();
+        if group >= limits.max_bind_groups {
+            return Err(AndError::InvalidUniform(format!(
+                "group {group} is past the device's {} bind groups", limits.max_bind_groups,
+            )));
+        }
+
+        let size = (data.len() as u64).max(1).next_multiple_of(Self::ALIGNMENT);
+        if size > limits.max_uniform_buffer_binding_size as u64 {
+            return Err(AndError::InvalidUniform(format!(
+                "{size} bytes is over the device's {} byte limit", limits.max_uniform_buffer_binding_size,
+            )));
+        }
+
+        if let Some(uniform) = self.groups.get_mut(&group).and_then(|group| group.bindings.get_mut(&binding)) {
+            if uniform.data.len() as u64 == size {
+                uniform.data[..data.len()].copy_from_slice(data);
+                uniform.current.fill(false);
+                uniform.write(queue, self.frame);
+                return Ok(false);
+            }
+        }
+
+        let mut padded = vec![0; size as usize];
+        padded[..data.len()].copy_from_slice(data);
+
+        let mut bindings = self.groups.remove(&group).map(|group| group.bindings).unwrap_or_default();
+        bindings.insert(binding, Binding::new(device, self.frames, padded));
+        self.groups.insert(group, Group::new(device, self.frames, bindings));
+        Ok(true)
+    }
+
+    /// Moves on to the next frame's buffers, bringing any that missed a write since their frame last came around up
+    /// to date. Call right after submitting a frame that used the current ones.
+    pub(crate) fn advance(&mut self, queue: &wgpu::Queue) {
+        self.frame = (self.frame + 1) % self.frames;
+        for uniform in self.groups.values_mut().flat_map(|group| group.bindings.values_mut()) {
+            if !uniform.current[self.frame] {
+                uniform.write(queue, self.frame);
+            }
+        }
+    }
+
+    /// Recreates every buffer and bind group on `device` with the values last set, e.g. after device loss, keeping
+    /// `frames` copies of each from then on.
+    pub(crate) fn recreate(&mut self, device: &wgpu::Device, frames: u32) {
+        self.frames = frames.max(1) as usize;
+        self.frame = 0;
+        for group in self.groups.values_mut() {
+            let bindings = std::mem::take(&mut group.bindings).into_iter()
+                .map(|(binding, uniform)| (binding, Binding::new(device, self.frames, uniform.data)))
+                .collect();
+            *group = Group::new(device, self.frames, bindings);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use wgpu::util::StagingBelt;
+
+/// Per-frame buffer uploads through a [`StagingBelt`], recorded into the frame's own encoder instead of going
+/// through [`wgpu::Queue::write_buffer`], which can stall on some backends.
+pub struct Uploader {
+    belt: StagingBelt,
+}
+
+impl Uploader {
+    const CHUNK_SIZE: wgpu::BufferAddress = 64 * 1024;
+
+    pub fn new() -> Self {
+        Self { belt: StagingBelt::new(Self::CHUNK_SIZE), }
+    }
+
+    /// Records a copy of `data` into `buffer` at `offset`. Must happen before [`Uploader::finish`].
+    pub fn write(
+        &mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer, offset: wgpu::BufferAddress, data: &[u8],
+    ) {
+        let Some(size) = wgpu::BufferSize::new(data.len() as wgpu::BufferAddress) else { return };
+        self.belt.write_buffer(encoder, buffer, offset, size, device).copy_from_slice(data);
+    }
+
+    /// Closes this frame's staging buffers. Call right before submitting the encoders written into.
+    pub fn finish(&mut self) {
+        self.belt.finish();
+    }
+
+    /// Reclaims staging buffers the GPU is done with. Call right after submitting.
+    pub fn recall(&mut self) {
+        self.belt.recall();
+    }
+}
+
+/// Distance between consecutive `T`s in a buffer bound with dynamic offsets: its size rounded up to the device's
+/// offset alignment, so every offset lands on a multiple of it.
+pub fn dynamic_stride<T>(device: &wgpu::Device) -> wgpu::BufferAddress {
+    let align = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+    (std::mem::size_of::<T>() as wgpu::BufferAddress).next_multiple_of(align.max(1))
+}